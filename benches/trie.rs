@@ -0,0 +1,208 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use ethers::{
+    types::{Bytes, H256},
+    utils::keccak256,
+};
+use zk_proof_of_evm_exploit::state_root::utils::{
+    reference_trie::ReferenceTrie, Nibbles, NodeData, Trie,
+};
+
+// Run with `cargo bench --features test-utils --bench trie` before and after a
+// refactor of the trie and compare. `--features test-utils,rayon` adds
+// apply_changes/50k_dirty_par, which can only get ahead of the serial one with
+// more than one core.
+
+// fixtures from the tests in src/state_root/utils/trie.rs
+const LEAF: &str = "0xe3a120290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e56308";
+const EXTENSION: &str =
+    "0xe583165a7ba0e46db0426b9d34c7b2df7baf0480777946e6b5b74a0572592b0229a4edaed944";
+const BRANCH: &str = "0xf851a0e97150c3ed221a6f46bdcd44e8a2d44825bc781fa48f797e9df2f8ceff52a43e8080808080808080808080a09487c8e7f28469b9f72cd6be094b555c3882c0653f11b208ff76bf8caee5043280808080";
+// root of the mainnet state trie at block 1000007, all 16 children present
+const BRANCH_FULL: &str = "0xf90211a0b872db6acc7578d800933b3feccc45efef5f2da00785fca59f289b34eb57ebbba08f76ef15b477fd42f2ca04c26cf29c83951abe5fb1fa1a60720168b714edb7cea0e3e790cd7cd981889d1e6e4cf63a3b30dc000f1691d45710b3909dedc0937e34a01420a38afb3f20254769a0fd30df5491335cea49c7987bd6d0def3f57a31d808a0c57482cbb52a96ee1fef320372d955a7a26bc63c8764cf8ccb174668669b441ba009a695239c731388ed07d5d6b72539f9417e6f0e52e0183b26d1f128abf8087ca02733233b16bfdb1dad2a36ef4a5a89e19775fffe59e32bd9dfffe9bcf3192d54a07cb5b5554f33dd6a9945762f2309e810784decf1d20b1b5ce2ea566950d47ebea08b98b75aed32960445816529bce7a6eae46f5d4acdd9eefb96c1d5454abe9ca9a09c74e85958f9fc02f811a2f2183fdbcf45348cc7f22d9b0b0016f02b671a5869a0e1a824538b465ee676365108f9ab960fcf8209c0ecf898f24e5cf4b6ffe19667a08ea30f24c5cbbe29eedf29518e601fe597f4ead30b6e7c6d206187df08d118a4a0daf637f97661b524ee658b44e5c775b1d15393a4d9e6fd697b3201b014d7a8cea0067eeff1881be674d5cbfc21385f16e99283e93d70f8becb7cbbccf814c26e33a04c755ff43db496e3ac08605a91129bb3acf31fb3cc6fa49ea3355e95f32f422ca0931ea32e24b1747103be9909335432fe7315b23ecad64224ced0c5c948e6db8a80";
+
+// (key, value, proof) from test_trie_new_{one,two,three}_element_1
+const LEVEL_1: (&str, &str, &[&str]) = (
+    "0x290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563",
+    "0x08",
+    &[LEAF],
+);
+const LEVEL_2: (&str, &str, &[&str]) = (
+    "0x036b6384b5eca791c62761152d0c79bb0604c104a5fb6f4eb0703f3154bb3db0",
+    "0x09",
+    &[
+        BRANCH,
+        "0xe2a0336b6384b5eca791c62761152d0c79bb0604c104a5fb6f4eb0703f3154bb3db009",
+    ],
+);
+const LEVEL_3: (&str, &str, &[&str]) = (
+    "0xc65a7bb8d6351c1cf70c95a316cc6a92839c986682d98bc35f958f4883f9d2a8",
+    "0x14",
+    &[
+        "0xf851a0c2af0751112c3efa2873802b452283ab1e2c60fde148a2f9e482ed03b8947e158080808080808080808080a0b3e6ad355d7116d0b4173e75e4c760082c8870e3b5b746cfadfea7101e834cc280808080",
+        EXTENSION,
+        "0xf85180808080808080a00c104f2019963f0df89d54742b14cd0ad7418cb208e9bc69bf80cb296926ffe9808080a04efd8a29c04796b9c9b13af2740864e48851a89ef4292575ab5f69b3a52c06c08080808080",
+        "0xdf9d38d6351c1cf70c95a316cc6a92839c986682d98bc35f958f4883f9d2a814",
+    ],
+);
+
+const BATCH_SIZE: u64 = 1000;
+
+fn parse_fixture(fixture: (&str, &str, &[&str])) -> (Nibbles, Bytes, Vec<Bytes>) {
+    let (key, value, proof) = fixture;
+    (
+        Nibbles::from_raw_path_str(key),
+        value.parse().unwrap(),
+        proof.iter().map(|node| node.parse().unwrap()).collect(),
+    )
+}
+
+// secure trie style keys, so that the proofs look like storage proofs
+fn batch() -> (H256, Vec<(Nibbles, Bytes, Vec<Bytes>)>) {
+    let mut reference = ReferenceTrie::new();
+    let entries = (0..BATCH_SIZE)
+        .map(|i| {
+            let key = keccak256(i.to_be_bytes());
+            let value = Bytes::from(keccak256(key)[..(i % 32 + 1) as usize].to_vec());
+            reference.insert(&key, &value).unwrap();
+            (key, value)
+        })
+        .collect::<Vec<_>>();
+
+    let entries = entries
+        .into_iter()
+        .map(|(key, value)| {
+            let proof = reference.get_proof(&key).unwrap();
+            (
+                Nibbles::from_raw_path(Bytes::from(key.to_vec())),
+                value,
+                proof,
+            )
+        })
+        .collect();
+    (reference.root().unwrap(), entries)
+}
+
+fn bench_node_data_new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("node_data_new");
+    for (name, raw) in [
+        ("leaf", LEAF),
+        ("extension", EXTENSION),
+        ("branch", BRANCH),
+        ("branch_full", BRANCH_FULL),
+    ] {
+        let raw: Bytes = raw.parse().unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| NodeData::from_raw_rlp(black_box(raw.clone())).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_load_proof(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_proof");
+    for (name, fixture) in [
+        ("1_level", LEVEL_1),
+        ("2_level", LEVEL_2),
+        ("3_level", LEVEL_3),
+    ] {
+        let (key, value, proof) = parse_fixture(fixture);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || (key.clone(), value.clone(), proof.clone()),
+                |(key, value, proof)| {
+                    let mut trie = Trie::new();
+                    trie.load_proof(key, value, proof).unwrap();
+                    trie
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    let (root, entries) = batch();
+    group.bench_function("batch_1000", |b| {
+        b.iter_batched(
+            || entries.clone(),
+            |entries| {
+                let mut trie = Trie::from_root(root);
+                for (key, value, proof) in entries {
+                    trie.load_proof(key, value, proof).unwrap();
+                }
+                trie
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+// a clone shares the nodes, the first change made to it copies them
+fn bench_clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone");
+    let (root, entries) = batch();
+    let mut trie = Trie::from_root(root);
+    for (key, value, proof) in entries.iter().cloned() {
+        trie.load_proof(key, value, proof).unwrap();
+    }
+    let (key, _, _) = entries[0].clone();
+
+    group.bench_function("batch_1000", |b| b.iter(|| black_box(&trie).clone()));
+    group.bench_function("batch_1000_set_value", |b| {
+        b.iter(|| {
+            let mut trie = black_box(&trie).clone();
+            trie.set_value(key.clone(), Bytes::from(vec![0x01]))
+                .unwrap();
+            trie
+        })
+    });
+    group.finish();
+}
+
+// a block worth of writes, every one of the 50k leaves changed, rehashed on one
+// thread and with the children of branches on the rayon thread pool
+fn bench_apply_changes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply_changes");
+    group.sample_size(10);
+    let path = |i: u64| Nibbles::from_raw_path(Bytes::from(keccak256(i.to_be_bytes()).to_vec()));
+    let mut trie = Trie::empty();
+    trie.apply_changes(
+        (0..50_000)
+            .map(|i| (path(i), Some(Bytes::from(vec![0x01]))))
+            .collect(),
+    )
+    .unwrap();
+    let changes = (0..50_000)
+        .map(|i| {
+            (
+                path(i),
+                Some(Bytes::from(H256::from_low_u64_be(i).as_bytes().to_vec())),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    group.bench_function("50k_dirty", |b| {
+        b.iter_batched(
+            || (trie.clone(), changes.clone()),
+            |(mut trie, changes)| trie.apply_changes(changes).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    #[cfg(feature = "rayon")]
+    group.bench_function("50k_dirty_par", |b| {
+        b.iter_batched(
+            || (trie.clone(), changes.clone()),
+            |(mut trie, changes)| trie.apply_changes_par(changes).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_node_data_new,
+    bench_load_proof,
+    bench_clone,
+    bench_apply_changes
+);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+#![no_main]
+
+use ethers::types::Bytes;
+use libfuzzer_sys::fuzz_target;
+use zk_proof_of_evm_exploit::state_root::utils::{Nibbles, Trie};
+
+// without a root the first entry is taken as the root node, so the descent goes
+// past the root check
+fuzz_target!(|input: (Vec<u8>, Vec<u8>, Vec<Vec<u8>>)| {
+    let (key, value, proof) = input;
+    let key = Nibbles::from_raw_path(Bytes::from(key));
+    let proof = proof.into_iter().map(Bytes::from).collect();
+
+    let mut trie = Trie::new();
+    if trie
+        .load_proof(key.clone(), Bytes::from(value), proof)
+        .is_ok()
+    {
+        let _ = trie.get_value(key);
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use ethers::types::Bytes;
+use libfuzzer_sys::fuzz_target;
+use zk_proof_of_evm_exploit::state_root::utils::{NodeData, NodeEncoding};
+
+// any bytes either decode to a node or error, canonical or not
+fuzz_target!(|raw: Vec<u8>| {
+    let raw = Bytes::from(raw);
+    for encoding in [NodeEncoding::Lenient, NodeEncoding::LenientStorage] {
+        if let Ok(node_data) = NodeData::new(raw.clone(), encoding) {
+            let _ = node_data.to_raw_rlp();
+        }
+    }
+    let _ = NodeData::new(raw, NodeEncoding::Canonical);
+});
@@ -0,0 +1,199 @@
+use clap::Parser;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Address, BigEndianHash, EIP1186ProofResponse, H256, U256},
+};
+use serde_json::{json, Value};
+use std::process::exit;
+use zk_proof_of_evm_exploit::{
+    error::Error,
+    state_root::{state_trie::StateTrie, utils::TrieStats},
+};
+
+/// Usage:
+/// ./target/release/proof-check
+///     --rpc-url https://eth-mainnet.g.alchemy.com/v2/<api_key>
+///     --block 1000007
+///     --address 0x2a65Aca4D5fC5B5C859090a6c34d164135398226
+///     [--slot 0]
+///     [--json]
+///
+/// Fetches the block header and `eth_getProof` for the account, verifies the proof
+/// against the header's state root and prints the verified fields. Exits with a
+/// nonzero code if anything does not verify.
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    // required args
+    #[arg(
+        long = "rpc-url",
+        help = "Archive node to fetch the proof from [required]"
+    )]
+    eth_rpc_url: String,
+    #[arg(
+        long,
+        help = "Block number whose state root is proven against [required]"
+    )]
+    block: u64,
+    #[arg(long, help = "Address of the account to prove [required]")]
+    address: Address,
+
+    // optional args
+    #[arg(long, help = "Storage slot of the account to prove")]
+    slot: Option<U256>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print machine-readable output [default: false]"
+    )]
+    json: bool,
+}
+
+struct Report {
+    state_root: H256,
+    proof: EIP1186ProofResponse,
+    // None when no slot was requested
+    storage_value: Option<U256>,
+    account_trie_stats: TrieStats,
+    // None when no slot was requested
+    storage_trie_stats: Option<TrieStats>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    match run(&args).await {
+        Ok(report) => {
+            if args.json {
+                println!("{}", report_json(&report));
+            } else {
+                print_report(&report);
+            }
+        }
+        Err(err) => {
+            if args.json {
+                println!("{}", json!({"verified": false, "error": err.to_string()}));
+            } else {
+                eprintln!("verification failed: {}", err);
+            }
+            exit(1);
+        }
+    }
+}
+
+async fn run(args: &Args) -> Result<Report, Error> {
+    let provider = Provider::<Http>::try_from(args.eth_rpc_url.as_str())
+        .map_err(|_| Error::InvalidRpcUrl(args.eth_rpc_url.clone()))?;
+
+    let block = provider
+        .get_block(args.block)
+        .await?
+        .ok_or(Error::BlockNotFound(args.block))?;
+
+    let locations = args.slot.iter().map(H256::from_uint).collect::<Vec<_>>();
+    let proof = provider
+        .get_proof(args.address, locations, Some(args.block.into()))
+        .await?;
+    if proof.address != args.address {
+        return Err(Error::ProofAddressMismatch(args.address, proof.address));
+    }
+
+    let mut trie = StateTrie::from_root(block.state_root);
+    trie.load_proof(proof.clone())?;
+
+    let (storage_value, storage_trie_stats) = match args.slot {
+        Some(slot) => {
+            let storage_trie = trie.get_storage_trie(proof.storage_hash);
+            (
+                Some(storage_trie.get_value(slot)?),
+                Some(storage_trie.stats()),
+            )
+        }
+        None => (None, None),
+    };
+
+    Ok(Report {
+        state_root: block.state_root,
+        proof,
+        storage_value,
+        account_trie_stats: trie.account_trie.stats(),
+        storage_trie_stats,
+    })
+}
+
+// (nodes, bytes) of a proof
+fn proof_size(proof: &EIP1186ProofResponse) -> (usize, usize) {
+    let nodes = proof.account_proof.iter().chain(
+        proof
+            .storage_proof
+            .iter()
+            .flat_map(|proof| proof.proof.iter()),
+    );
+    nodes.fold((0, 0), |(count, bytes), node| {
+        (count + 1, bytes + node.len())
+    })
+}
+
+fn print_report(report: &Report) {
+    let proof = &report.proof;
+    println!("state root:   {:?}", report.state_root);
+    println!("address:      {:?}", proof.address);
+    println!("nonce:        {}", proof.nonce);
+    println!("balance:      {}", proof.balance);
+    println!("storage root: {:?}", proof.storage_hash);
+    println!("code hash:    {:?}", proof.code_hash);
+    if let Some(value) = report.storage_value {
+        if value.is_zero() {
+            println!("storage:      provably absent");
+        } else {
+            println!("storage:      {:#x}", value);
+        }
+    }
+    let (nodes, bytes) = proof_size(proof);
+    println!("proof:        {} nodes, {} bytes", nodes, bytes);
+    print_stats("account trie:", &report.account_trie_stats);
+    if let Some(stats) = &report.storage_trie_stats {
+        print_stats("storage trie:", stats);
+    }
+}
+
+fn print_stats(label: &str, stats: &TrieStats) {
+    println!(
+        "{:<13} {} nodes ({} leaves, {} branches, {} extensions), depth {}, {} unknown",
+        label,
+        stats.nodes,
+        stats.leaves,
+        stats.branches,
+        stats.extensions,
+        stats.max_depth,
+        stats.unknown
+    );
+}
+
+fn report_json(report: &Report) -> Value {
+    let proof = &report.proof;
+    let (nodes, bytes) = proof_size(proof);
+    let mut json = json!({
+        "verified": true,
+        "state_root": format!("{:?}", report.state_root),
+        "address": format!("{:?}", proof.address),
+        "nonce": format!("{:#x}", proof.nonce),
+        "balance": format!("{:#x}", proof.balance),
+        "storage_root": format!("{:?}", proof.storage_hash),
+        "code_hash": format!("{:?}", proof.code_hash),
+        "proof_nodes": nodes,
+        "proof_bytes": bytes,
+        "account_trie_stats": report.account_trie_stats,
+    });
+    if let Some(value) = report.storage_value {
+        json["storage_value"] = if value.is_zero() {
+            Value::Null
+        } else {
+            json!(format!("{:#x}", value))
+        };
+        json["absent"] = json!(value.is_zero());
+        json["storage_trie_stats"] = json!(report.storage_trie_stats);
+    }
+    json
+}
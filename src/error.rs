@@ -1,16 +1,365 @@
+use std::{fmt, path::PathBuf};
+
 use anvil::eth::error::BlockchainError;
-use ethers_core::utils::rlp;
+use ethers::{
+    providers::ProviderError,
+    types::{Address, Bytes, H256, U256},
+    utils::{hex, rlp},
+};
 use halo2_proofs::plonk;
 
+use crate::state_root::utils::{LeafKind, LimitKind, Nibbles, NodeKind};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub enum Error {
     AnvilError(BlockchainError),
-    RlpDecoderError(rlp::DecoderError),
+    // (what was being decoded, error) for the RLP and hex errors
+    RlpDecoderError(&'static str, rlp::DecoderError),
+    HexError(&'static str, hex::FromHexError),
+    JsonError(serde_json::Error),
     BusMappingError(bus_mapping::Error),
+    ProviderError(ProviderError),
     Halo2Error(plonk::Error),
     StdError(std::io::Error),
-    InternalError(&'static str),
+    InvalidNibble(u8),
+    MissingTrieNode(H256),
+    NonCanonicalRlp(usize),
+    ProofOutOfOrder,
+    RootNotSet,
+    DuplicateAccount(Address),
+    // (limit, maximum) a load of proofs would cross
+    LimitExceeded(LimitKind, usize),
+    // field count of a block header of no known hardfork
+    UnsupportedHeaderFields(usize),
+    // error from loading the fixture file at the path
+    FixtureError(PathBuf, Box<Error>),
+    // error from the proof of the key at the index of a multiproof
+    MultiproofKeyError(usize, Box<Error>),
+    // (expected, found) for the hash of a proof entry
+    ProofHashMismatch(H256, H256),
+    // index of a proof entry that is not on the path of the key
+    UnusedProofEntry(usize),
+    // the child at the nibble of the key, if any, does not match the hash found for
+    // the next proof entry
+    ProofChildMissing {
+        nibble: u8,
+        expected: Option<H256>,
+        found: H256,
+    },
+    // (in the trie, input) for the value a key resolves to
+    ValueMismatch(Bytes, Bytes),
+    // (loaded, input) for the value of a key the loaded nodes already prove
+    ConflictingProof(Bytes, Bytes),
+    // (expected, leaf value) for a leaf of a proof
+    UnexpectedLeafShape(LeafKind, Bytes),
+    // (missing, unexpected) proven keys of a trie against the expected ones
+    KeySetMismatch(Vec<Bytes>, Vec<Bytes>),
+    // (old, new) nonce of an account that went down between two states
+    NonceDecreased(U256, U256),
+    // (old, new) balance of an account without code lost with no transaction sent
+    BalanceDecreased(U256, U256),
+    // state root that is in none of the trusted checkpoints
+    UntrustedRoot(H256),
+    // (expected, found) for the checks of a state transition between two blocks
+    PreStateRootMismatch(H256, H256),
+    ParentHashMismatch(H256, H256),
+    PostStateRootMismatch(H256, H256),
+    // nonce of an account that does not fit the u64 of an eth_getProof response
+    NonceOverflow(U256),
+    // reason a trie node is rejected, with its RLP or the item of it that is wrong
+    InvalidNode(&'static str, Bytes),
+    // kind of the node at the hash, which cannot be where it was found
+    UnexpectedNode(NodeKind, H256),
+    // reason a proof does not prove the key
+    InvalidProof(&'static str, Nibbles),
+    // hex prefix encoded path that does not decode
+    InvalidEncodedPath(Bytes),
+    // (from, length) for a slice of nibbles that starts past their end
+    SliceOutOfRange(usize, usize),
+    // bytes that are not the 32 of a hash
+    InvalidHashLength(Bytes),
+    // number of nibbles of a full key that are not whole bytes
+    OddNibbleCount(usize),
+    // RLP of a new node under 32 bytes, which Ethereum would inline into its parent
+    InlineNode(Bytes),
+    // number of children given for a branch
+    BranchChildCount(usize),
+    // length in nibbles of a path that ends at a branch, where its next nibble is
+    // needed
+    PathEndsAtBranch(usize),
+    // (key, other) for a key that is a prefix of another key
+    KeyIsPrefix(Nibbles, Nibbles),
+    // (index, length) for a key or node index past the end of a multiproof
+    MultiproofIndexOutOfRange(usize, usize),
+    // (given, in the multiproof) number of keys of a multiproof
+    MultiproofKeyCount(usize, usize),
+    // number of multiproof nodes on the path of no key
+    UnusedMultiproofNodes(usize),
+    RootAlreadySet(H256),
+    // checkpoint or snapshot that was never taken or is reverted
+    CheckpointNotFound(usize),
+    // (reason, offset) for a proof batch that does not decode
+    InvalidProofBatch(&'static str, usize),
+    // length or count that does not fit the u32 of the proof batch framing
+    ProofBatchTooLarge(usize),
+    // (balance, amount) for an addition to a balance that overflows
+    BalanceOverflow(U256, U256),
+    // (requested, found) address of a proof response
+    ProofAddressMismatch(Address, Address),
+    // index of a transaction with no receipt in the block
+    ReceiptNotFound(u64),
+    // (index, number of logs) for a log that is not in the receipt
+    LogNotFound(usize, usize),
+    // (current, given) storage root of an account written through set_account
+    StorageRootChanged(H256, H256),
+    // field of a block or header from the node that is not set
+    IncompleteBlock(&'static str),
+    ChainIdUnavailable,
+    BlockNotFound(u64),
+    InvalidRpcUrl(String),
+    // error of the reference trie, formatted
+    ReferenceTrieError(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::AnvilError(err) => write!(f, "anvil: {}", err),
+            Error::RlpDecoderError(what, err) => {
+                write!(f, "rlp decoding of the {} failed: {}", what, err)
+            }
+            Error::HexError(what, err) => write!(f, "hex decoding of the {} failed: {}", what, err),
+            Error::JsonError(err) => write!(f, "json decoding failed: {}", err),
+            Error::BusMappingError(err) => write!(f, "bus mapping: {}", err),
+            Error::ProviderError(err) => write!(f, "provider: {}", err),
+            Error::Halo2Error(err) => write!(f, "halo2: {}", err),
+            Error::StdError(err) => write!(f, "io: {}", err),
+            Error::InvalidNibble(nibble) => write!(f, "invalid nibble {:#x}", nibble),
+            Error::MissingTrieNode(hash) => {
+                write!(f, "trie node {:?} is not loaded, please add a proof", hash)
+            }
+            Error::NonCanonicalRlp(offset) => {
+                write!(f, "rlp is not canonical, differs at byte {}", offset)
+            }
+            Error::ProofOutOfOrder => write!(f, "proof entries are not ordered from the root"),
+            Error::RootNotSet => write!(f, "root not set"),
+            Error::DuplicateAccount(address) => {
+                write!(f, "account {:?} is already loaded", address)
+            }
+            Error::LimitExceeded(limit, max) => {
+                write!(f, "loading exceeds the {:?} limit of {}", limit, max)
+            }
+            Error::UnsupportedHeaderFields(fields) => {
+                write!(f, "block header with {} fields is not supported", fields)
+            }
+            Error::FixtureError(path, err) => write!(f, "fixture {}: {}", path.display(), err),
+            Error::MultiproofKeyError(index, err) => write!(f, "multiproof key {}: {}", index, err),
+            Error::ProofHashMismatch(expected, found) => write!(
+                f,
+                "proof entry hash {:?} does not match the node root {:?}",
+                found, expected
+            ),
+            Error::UnusedProofEntry(index) => {
+                write!(f, "proof entry {} is not on the path of the key", index)
+            }
+            Error::ProofChildMissing {
+                nibble,
+                expected: Some(expected),
+                found,
+            } => write!(
+                f,
+                "branch child {:?} at nibble {:#x} does not match the next proof entry {:?}",
+                expected, nibble, found
+            ),
+            Error::ProofChildMissing {
+                nibble,
+                expected: None,
+                found,
+            } => write!(
+                f,
+                "branch has no child at nibble {:#x} for the next proof entry {:?}",
+                nibble, found
+            ),
+            Error::ValueMismatch(in_trie, input) => write!(
+                f,
+                "value {} in the trie does not match the input {}",
+                in_trie, input
+            ),
+            Error::ConflictingProof(loaded, input) => write!(
+                f,
+                "value {} already loaded for the key conflicts with the proof of {}",
+                loaded, input
+            ),
+            Error::UnexpectedLeafShape(expected, value) => write!(
+                f,
+                "leaf value {} is not of the expected kind {:?}",
+                value, expected
+            ),
+            Error::KeySetMismatch(missing, unexpected) => write!(
+                f,
+                "proven keys do not match the expected ones, missing [{}], unexpected [{}]",
+                join_keys(missing),
+                join_keys(unexpected)
+            ),
+            Error::NonceDecreased(old, new) => {
+                write!(f, "account nonce went down from {} to {}", old, new)
+            }
+            Error::BalanceDecreased(old, new) => write!(
+                f,
+                "balance of an account without code went down from {} to {} with the same nonce",
+                old, new
+            ),
+            Error::UntrustedRoot(root) => {
+                write!(f, "state root {:?} is not a trusted checkpoint", root)
+            }
+            Error::PreStateRootMismatch(expected, found) => write!(
+                f,
+                "pre state root {:?} does not match the expected {:?}",
+                found, expected
+            ),
+            Error::ParentHashMismatch(expected, found) => write!(
+                f,
+                "parent hash {:?} does not match the parent header hash {:?}",
+                found, expected
+            ),
+            Error::PostStateRootMismatch(expected, found) => write!(
+                f,
+                "computed state root {:?} does not match the header state root {:?}",
+                found, expected
+            ),
+            Error::NonceOverflow(nonce) => {
+                write!(f, "nonce {} does not fit in 64 bits", nonce)
+            }
+            Error::InvalidNode(reason, data) => write!(f, "invalid trie node {}: {}", data, reason),
+            Error::UnexpectedNode(kind, hash) => {
+                write!(f, "unexpected {:?} node {:?}", kind, hash)
+            }
+            Error::InvalidProof(reason, key) => {
+                write!(f, "proof of key {}: {}", nibbles_hex(key), reason)
+            }
+            Error::InvalidEncodedPath(path) => write!(f, "invalid encoded path {}", path),
+            Error::SliceOutOfRange(from, len) => {
+                write!(f, "slice from {} is past the {} nibbles", from, len)
+            }
+            Error::InvalidHashLength(bytes) => write!(
+                f,
+                "expected 32 bytes for a hash, got {} bytes {}",
+                bytes.len(),
+                bytes
+            ),
+            Error::OddNibbleCount(len) => {
+                write!(f, "odd number {} of nibbles for a full key", len)
+            }
+            Error::InlineNode(raw) => write!(
+                f,
+                "node {} is under 32 bytes, inlining it into its parent is not supported",
+                raw
+            ),
+            Error::BranchChildCount(count) => {
+                write!(
+                    f,
+                    "branch needs 16 children and an optional value, got {}",
+                    count
+                )
+            }
+            Error::PathEndsAtBranch(len) => {
+                write!(f, "path of {} nibbles ended at a branch", len)
+            }
+            Error::KeyIsPrefix(key, other) => write!(
+                f,
+                "key {} is a prefix of key {}",
+                nibbles_hex(key),
+                nibbles_hex(other)
+            ),
+            Error::MultiproofIndexOutOfRange(index, len) => {
+                write!(f, "multiproof index {} is out of range {}", index, len)
+            }
+            Error::MultiproofKeyCount(given, found) => {
+                write!(f, "multiproof is for {} keys, {} given", found, given)
+            }
+            Error::UnusedMultiproofNodes(count) => {
+                write!(f, "multiproof has {} nodes that no key uses", count)
+            }
+            Error::RootAlreadySet(root) => write!(f, "root {:?} already set", root),
+            Error::CheckpointNotFound(id) => {
+                write!(f, "checkpoint {} not found, it was reverted", id)
+            }
+            Error::InvalidProofBatch(reason, offset) => {
+                write!(f, "proof batch {} at byte {}", reason, offset)
+            }
+            Error::ProofBatchTooLarge(len) => {
+                write!(
+                    f,
+                    "proof batch cannot hold a length of {}, over u32::MAX",
+                    len
+                )
+            }
+            Error::BalanceOverflow(balance, amount) => {
+                write!(f, "balance {} overflows when adding {}", balance, amount)
+            }
+            Error::ProofAddressMismatch(requested, found) => write!(
+                f,
+                "proof is for {:?}, not the requested {:?}",
+                found, requested
+            ),
+            Error::ReceiptNotFound(index) => {
+                write!(f, "no receipt at index {} in the block", index)
+            }
+            Error::LogNotFound(index, logs) => write!(
+                f,
+                "no log at index {} in a receipt with {} logs",
+                index, logs
+            ),
+            Error::StorageRootChanged(current, given) => write!(
+                f,
+                "storage root {:?} differs from {:?}, it only changes through set_storage_value",
+                given, current
+            ),
+            Error::IncompleteBlock(field) => write!(f, "{} is not set", field),
+            Error::ChainIdUnavailable => write!(f, "unable to get the chain id from the node"),
+            Error::BlockNotFound(number) => write!(f, "block {} not found", number),
+            Error::InvalidRpcUrl(url) => write!(f, "invalid rpc url {}", url),
+            Error::ReferenceTrieError(err) => write!(f, "reference trie: {}", err),
+        }
+    }
+}
+
+// nibbles as hex digits, one per nibble
+fn nibbles_hex(nibbles: &Nibbles) -> String {
+    let digits = nibbles
+        .to_u4_vec()
+        .iter()
+        .map(|nibble| format!("{:x}", nibble))
+        .collect::<String>();
+    format!("0x{}", digits)
+}
+
+fn join_keys(keys: &[Bytes]) -> String {
+    keys.iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::AnvilError(err) => Some(err),
+            Error::RlpDecoderError(_, err) => Some(err),
+            Error::HexError(_, err) => Some(err),
+            Error::JsonError(err) => Some(err),
+            Error::BusMappingError(err) => Some(err),
+            Error::ProviderError(err) => Some(err),
+            Error::Halo2Error(err) => Some(err),
+            Error::StdError(err) => Some(err),
+            Error::FixtureError(_, err) => Some(err.as_ref()),
+            Error::MultiproofKeyError(_, err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
 }
 
 impl From<BlockchainError> for Error {
@@ -25,9 +374,45 @@ impl From<bus_mapping::Error> for Error {
     }
 }
 
+impl From<ProviderError> for Error {
+    fn from(err: ProviderError) -> Self {
+        Error::ProviderError(err)
+    }
+}
+
+// plain conversions for callers with no better context, prefer `.decoding(what)`
 impl From<rlp::DecoderError> for Error {
     fn from(err: rlp::DecoderError) -> Self {
-        Error::RlpDecoderError(err)
+        Error::RlpDecoderError("input", err)
+    }
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(err: hex::FromHexError) -> Self {
+        Error::HexError("input", err)
+    }
+}
+
+/// Names what was being decoded, so that an RLP or hex error says where it failed.
+pub trait DecodeContext<T> {
+    fn decoding(self, what: &'static str) -> Result<T>;
+}
+
+impl<T> DecodeContext<T> for std::result::Result<T, rlp::DecoderError> {
+    fn decoding(self, what: &'static str) -> Result<T> {
+        self.map_err(|err| Error::RlpDecoderError(what, err))
+    }
+}
+
+impl<T> DecodeContext<T> for std::result::Result<T, hex::FromHexError> {
+    fn decoding(self, what: &'static str) -> Result<T> {
+        self.map_err(|err| Error::HexError(what, err))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::JsonError(err)
     }
 }
 
@@ -42,3 +427,46 @@ impl From<std::io::Error> for Error {
         Error::StdError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{hex, rlp, DecodeContext, Error, Nibbles, H256};
+    use std::error::Error as _;
+
+    #[test]
+    pub fn test_error_display_1() {
+        let err = Error::MissingTrieNode(H256::repeat_byte(0xab));
+        assert_eq!(
+            err.to_string(),
+            "trie node 0xabababababababababababababababababababababababababababababababab is not loaded, please add a proof"
+        );
+        assert!(err.source().is_none());
+
+        let err = Err::<(), _>(rlp::DecoderError::RlpIsTooShort)
+            .decoding("account")
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("rlp decoding of the account failed: "));
+        assert!(err.source().is_some());
+
+        let err = Error::from(rlp::DecoderError::RlpIsTooShort);
+        assert!(err
+            .to_string()
+            .starts_with("rlp decoding of the input failed: "));
+        let err = Error::from(hex::FromHexError::OddLength);
+        assert_eq!(
+            err.to_string(),
+            "hex decoding of the input failed: Odd number of digits"
+        );
+
+        let err = Error::InvalidProof(
+            "key in leaf does not match input",
+            Nibbles::from_raw_path_str("0x12ab"),
+        );
+        assert_eq!(
+            err.to_string(),
+            "proof of key 0x12ab: key in leaf does not match input"
+        );
+    }
+}
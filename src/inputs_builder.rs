@@ -38,9 +38,7 @@ pub fn get_state_accesses(
         None,
         RW::WRITE,
         AccessValue::Account {
-            address: block
-                .author
-                .ok_or(Error::InternalError("Incomplete block"))?,
+            address: block.author.ok_or(Error::IncompleteBlock("block author"))?,
         },
     )];
     for (tx_index, tx) in block.transactions.iter().enumerate() {
@@ -80,9 +78,7 @@ impl BuilderClient {
                 circuits_params,
             })
         } else {
-            Err(Error::InternalError(
-                "Unable to get chain id from ETH client",
-            ))
+            Err(Error::ChainIdUnavailable)
         }
     }
 
@@ -167,9 +163,7 @@ impl BuilderClient {
             }
 
             // latest block hash is the last item
-            let block_hash = header
-                .hash
-                .ok_or(Error::InternalError("Incomplete block"))?;
+            let block_hash = header.hash.ok_or(Error::IncompleteBlock("block hash"))?;
             history_hashes.push(h256_to_u256(block_hash));
         }
 
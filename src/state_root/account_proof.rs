@@ -0,0 +1,680 @@
+use ethers::types::{
+    Address, BigEndianHash, Bytes, EIP1186ProofResponse, StorageProof, H256, U256, U64,
+};
+
+use super::{
+    account_trie::{AccountData, AccountTrie, EMPTY_CODE_HASH},
+    storage_trie::StorageTrie,
+    utils::{empty_root, pad32, Nibbles, NodeData, Trie},
+};
+use crate::error::Error;
+
+/// An account proof along with the storage proofs of the account, bound to the
+/// state root they are proven against.
+#[derive(Clone, Debug)]
+pub struct AccountProof {
+    pub address: Address,
+    pub state_root: H256,
+    pub account: AccountData,
+    pub proof: Vec<Bytes>,
+    pub storage_proofs: Vec<StorageSlotProof>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageSlotProof {
+    pub slot: U256,
+    pub value: U256,
+    pub proof: Vec<Bytes>,
+}
+
+/// Account fields and storage values after all proofs of an `AccountProof` have
+/// been checked.
+#[derive(Clone, Debug)]
+pub struct VerifiedAccount {
+    pub address: Address,
+    // root the account proof was checked against
+    pub state_root: H256,
+    pub account: AccountData,
+    // (slot, value), in the order of the storage proofs
+    pub storage: Vec<(U256, U256)>,
+}
+
+impl AccountProof {
+    // eth_getProof does not include the state root, it is the one of the requested block
+    pub fn from_eip1186(state_root: H256, response: EIP1186ProofResponse) -> Self {
+        let mut account = AccountData {
+            nonce: U256::from(response.nonce.as_u64()),
+            balance: response.balance,
+            storage_root: response.storage_hash,
+            code_hash: response.code_hash,
+        };
+        // for an address the state does not hold, older geth returns zero hashes in
+        // place of the empty root and the empty code hash
+        let zero_or = |hash: H256, empty: H256| hash.is_zero() || hash == empty;
+        if account.nonce.is_zero()
+            && account.balance.is_zero()
+            && zero_or(account.storage_root, empty_root())
+            && zero_or(account.code_hash, EMPTY_CODE_HASH)
+        {
+            account = AccountData::empty();
+        }
+        AccountProof {
+            address: response.address,
+            state_root,
+            account,
+            proof: response.account_proof,
+            storage_proofs: response
+                .storage_proof
+                .into_iter()
+                .map(|storage_proof| StorageSlotProof {
+                    slot: U256::from_big_endian(storage_proof.key.as_bytes()),
+                    value: storage_proof.value,
+                    proof: storage_proof.proof,
+                })
+                .collect(),
+        }
+    }
+
+    /// Back to the shape of the `eth_getProof` response, without the state root.
+    /// A nonce above `u64::MAX` does not fit the response and errors with
+    /// `Error::NonceOverflow`.
+    pub fn to_eip1186(&self) -> Result<EIP1186ProofResponse, Error> {
+        if self.account.nonce > U256::from(u64::MAX) {
+            return Err(Error::NonceOverflow(self.account.nonce));
+        }
+        let nonce = self.account.nonce.as_u64();
+        Ok(EIP1186ProofResponse {
+            address: self.address,
+            balance: self.account.balance,
+            code_hash: self.account.code_hash,
+            nonce: U64::from(nonce),
+            storage_hash: self.account.storage_root,
+            account_proof: self.proof.clone(),
+            storage_proof: self
+                .storage_proofs
+                .iter()
+                .map(|storage_proof| StorageProof {
+                    key: H256::from_uint(&storage_proof.slot),
+                    proof: storage_proof.proof.clone(),
+                    value: storage_proof.value,
+                })
+                .collect(),
+        })
+    }
+
+    /// The `eth_getProof` response as geth returns it, every field hex encoded with
+    /// a `0x` prefix.
+    pub fn to_json(&self) -> Result<serde_json::Value, Error> {
+        Ok(serde_json::to_value(self.to_eip1186()?).expect("proof response serializes to json"))
+    }
+
+    /// Whether the proof excludes the address from the state trie. `eth_getProof`
+    /// answers for such an address with the fields of an empty account, and a
+    /// proof that does not end at a leaf of it.
+    pub fn is_absent(&self) -> Result<bool, Error> {
+        let path = Nibbles::from_address(self.address)?;
+        Ok(self.account == AccountData::empty() && proven_leaf(&path, &self.proof)?.is_none())
+    }
+
+    /// Checks the account proof against the state root, and every storage proof
+    /// against the storage root of the account. An account proven absent is
+    /// returned as an empty account.
+    pub fn verify(&self) -> Result<VerifiedAccount, Error> {
+        let mut account_trie = AccountTrie::from_root(self.state_root);
+        if self.is_absent()? {
+            account_trie.load_absence_proof(self.address, self.proof.clone())?;
+        } else {
+            account_trie.load_proof(self.address, self.account.clone(), self.proof.clone())?;
+        }
+
+        let mut storage_trie = StorageTrie::from_root(self.account.storage_root);
+        for storage_proof in self.storage_proofs.iter() {
+            storage_trie.load_proof(
+                storage_proof.slot,
+                storage_proof.value,
+                storage_proof.proof.clone(),
+            )?;
+        }
+
+        Ok(VerifiedAccount {
+            address: self.address,
+            state_root: self.state_root,
+            account: self.account.clone(),
+            storage: self
+                .storage_proofs
+                .iter()
+                .map(|storage_proof| (storage_proof.slot, storage_proof.value))
+                .collect(),
+        })
+    }
+}
+
+/// Verifies an `eth_getProof` response against a trusted state root, such as
+/// the one of a trusted header. The response carries no state root of its own.
+impl TryFrom<(H256, &EIP1186ProofResponse)> for VerifiedAccount {
+    type Error = Error;
+
+    fn try_from((state_root, response): (H256, &EIP1186ProofResponse)) -> Result<Self, Error> {
+        AccountProof::from_eip1186(state_root, response.clone()).verify()
+    }
+}
+
+impl TryFrom<(H256, EIP1186ProofResponse)> for VerifiedAccount {
+    type Error = Error;
+
+    fn try_from((state_root, response): (H256, EIP1186ProofResponse)) -> Result<Self, Error> {
+        AccountProof::from_eip1186(state_root, response).verify()
+    }
+}
+
+/// Checks `proof` for `address` against `state_root` and returns the account, or
+/// `None` if the proof excludes the address from the state trie.
+pub fn verify_account(
+    state_root: H256,
+    address: Address,
+    proof: Vec<Bytes>,
+) -> Result<Option<AccountData>, Error> {
+    let path = Nibbles::from_address(address)?;
+    let value = proven_leaf(&path, &proof)?.unwrap_or_default();
+
+    Trie::from_root(state_root).load_proof(path, value.clone(), proof)?;
+    if value.is_empty() {
+        Ok(None)
+    } else {
+        AccountData::from_rlp(value).map(Some)
+    }
+}
+
+// the account is present only if the proof ends at a leaf holding the rest of
+// its path, anything else has to be an exclusion proof
+fn proven_leaf(path: &Nibbles, proof: &[Bytes]) -> Result<Option<Bytes>, Error> {
+    Ok(match proof.last() {
+        Some(raw) => match NodeData::from_canonical_rlp(raw.clone())? {
+            NodeData::Leaf { key, value } if path.to_u4_vec().ends_with(&key.to_u4_vec()) => {
+                Some(value)
+            }
+            _ => None,
+        },
+        None => None,
+    })
+}
+
+/// What an account proof shows of an address. An empty account (see
+/// `AccountData::is_empty`) has a leaf of its own while an absent one has none,
+/// even though the EVM treats both alike in most places.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccountStatus {
+    Absent,
+    Empty,
+    Present(AccountData),
+}
+
+/// Same as `verify_account`, telling an account proven absent from one proven
+/// present but empty.
+pub fn verify_account_status(
+    state_root: H256,
+    address: Address,
+    proof: Vec<Bytes>,
+) -> Result<AccountStatus, Error> {
+    Ok(match verify_account(state_root, address, proof)? {
+        None => AccountStatus::Absent,
+        Some(account) if account.is_empty() => AccountStatus::Empty,
+        Some(account) => AccountStatus::Present(account),
+    })
+}
+
+/// Checks the account and all storage proofs of an `eth_getProof` response, and
+/// returns the account with the value of each requested slot.
+pub fn verify_account_with_slots(
+    state_root: H256,
+    response: EIP1186ProofResponse,
+) -> Result<(AccountData, Vec<(H256, U256)>), Error> {
+    let verified = AccountProof::from_eip1186(state_root, response).verify()?;
+    let slots = verified
+        .storage
+        .into_iter()
+        .map(|(slot, value)| (H256::from(pad32(slot)), value))
+        .collect();
+    Ok((verified.account, slots))
+}
+
+/// Checks that the nonce of an account did not go down from `old` to `new`, as it
+/// does when the later state is on another fork or rolled back.
+pub fn verify_nonce_monotonic(old: &AccountData, new: &AccountData) -> Result<(), Error> {
+    if new.nonce < old.nonce {
+        return Err(Error::NonceDecreased(old.nonce, new.nonce));
+    }
+    Ok(())
+}
+
+/// Checks the proofs of `address` against the state roots of two blocks, and that
+/// the account can have gone from the old state to the new one: its nonce did not
+/// go down, and without code it only lost balance by sending a transaction. An
+/// account absent from the old state starts out empty, one absent from the new
+/// state was deleted and is not checked. Returns both states of the account.
+pub fn verify_account_transition(
+    old_root: H256,
+    new_root: H256,
+    address: Address,
+    old_proof: Vec<Bytes>,
+    new_proof: Vec<Bytes>,
+) -> Result<(Option<AccountData>, Option<AccountData>), Error> {
+    let old = verify_account(old_root, address, old_proof)?;
+    let new = verify_account(new_root, address, new_proof)?;
+
+    if let Some(new) = &new {
+        let old = old.clone().unwrap_or_else(AccountData::empty);
+        verify_nonce_monotonic(&old, new)?;
+        // a contract can send value on a call, an account without code only with a
+        // transaction, which bumps the nonce
+        let without_code = old.code_hash == EMPTY_CODE_HASH && new.code_hash == EMPTY_CODE_HASH;
+        if without_code && new.nonce == old.nonce && new.balance < old.balance {
+            return Err(Error::BalanceDecreased(old.balance, new.balance));
+        }
+    }
+    Ok((old, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        pad32, verify_account, verify_account_status, verify_account_transition,
+        verify_account_with_slots, verify_nonce_monotonic, AccountData, AccountProof,
+        AccountStatus, StorageSlotProof, VerifiedAccount, EMPTY_CODE_HASH, U256,
+    };
+    use crate::{
+        error::Error,
+        state_root::utils::{Nibbles, NodeData},
+    };
+    use ethers::types::{Address, Bytes, EIP1186ProofResponse, StorageProof, H256};
+
+    // the account proofs from test_state_1, a contract deployed on geth --dev
+    const STATE_ROOT: &str = "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909";
+    const ROOT_NODE: &str = "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080";
+    const STORAGE_ROOT_NODE: &str = "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc5808080808080a0236e8f61ecde6abfebc6c529441f782f62469d8a2cc47b7aace2c136bd3b1ff08080808080";
+
+    fn parse_proof(proof: &[&str]) -> Vec<Bytes> {
+        proof.iter().map(|node| node.parse().unwrap()).collect()
+    }
+
+    fn contract_proof() -> AccountProof {
+        AccountProof {
+            address: "0x730E01e70B028b44a9387119d78E1392E4848Cbc".parse().unwrap(),
+            state_root: STATE_ROOT.parse().unwrap(),
+            account: AccountData {
+                nonce: U256::from(1),
+                balance: U256::zero(),
+                storage_root: "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+                    .parse()
+                    .unwrap(),
+                code_hash: "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16"
+                    .parse()
+                    .unwrap(),
+            },
+            proof: parse_proof(&[
+                ROOT_NODE,
+                "0xf869a03a7a2ee9b4f54ecbf2e04737a19215c0864d20c9a332db61d093e9ec95b2e87ab846f8440180a029cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748ca02e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16",
+            ]),
+            storage_proofs: vec![
+                StorageSlotProof {
+                    slot: U256::from(1),
+                    value: U256::from(2),
+                    proof: parse_proof(&[
+                        STORAGE_ROOT_NODE,
+                        "0xe2a0310e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf602",
+                    ]),
+                },
+                StorageSlotProof {
+                    slot: U256::from(2),
+                    value: U256::from(4),
+                    proof: parse_proof(&[
+                        STORAGE_ROOT_NODE,
+                        "0xe2a0305787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace04",
+                    ]),
+                },
+            ],
+        }
+    }
+
+    fn sender_proof() -> AccountProof {
+        AccountProof {
+            address: "0x3736b9d9d35d8c4f41d98a412fe9211024453575".parse().unwrap(),
+            state_root: STATE_ROOT.parse().unwrap(),
+            account: AccountData {
+                nonce: U256::from(3),
+                balance: "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82"
+                    .parse()
+                    .unwrap(),
+                storage_root: "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+                    .parse()
+                    .unwrap(),
+                code_hash: "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+                    .parse()
+                    .unwrap(),
+            },
+            proof: parse_proof(&[
+                ROOT_NODE,
+                "0xf889a03e19976962fea3751225213669050369b7cd26650bc43815007705e945b5aa57b866f86403a0ffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a0c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+            ]),
+            storage_proofs: vec![],
+        }
+    }
+
+    #[test]
+    pub fn test_account_proof_verify_1() {
+        let verified = contract_proof().verify().unwrap();
+        assert_eq!(
+            verified.address,
+            "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+                .parse::<Address>()
+                .unwrap()
+        );
+        assert_eq!(verified.account.nonce, U256::from(1));
+        assert_eq!(
+            verified.storage,
+            vec![
+                (U256::from(1), U256::from(2)),
+                (U256::from(2), U256::from(4))
+            ]
+        );
+
+        let verified = sender_proof().verify().unwrap();
+        assert_eq!(verified.account.nonce, U256::from(3));
+        assert!(verified.storage.is_empty());
+    }
+
+    #[test]
+    pub fn test_verify_account_1_present() {
+        let expected = sender_proof();
+        let account = verify_account(expected.state_root, expected.address, expected.proof)
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.nonce, expected.account.nonce);
+        assert_eq!(account.balance, expected.account.balance);
+        assert_eq!(account.storage_root, expected.account.storage_root);
+        assert_eq!(account.code_hash, expected.account.code_hash);
+    }
+
+    #[test]
+    pub fn test_verify_account_2_absent_empty_slot() {
+        // keccak(0x..0d) starts with nibble f, an empty slot of the root branch
+        let account = verify_account(
+            STATE_ROOT.parse().unwrap(),
+            "0x000000000000000000000000000000000000000d"
+                .parse()
+                .unwrap(),
+            parse_proof(&[ROOT_NODE]),
+        )
+        .unwrap();
+        assert!(account.is_none());
+    }
+
+    #[test]
+    pub fn test_verify_account_3_absent_diverging_leaf() {
+        // keccak(0x..14) starts with nibble 0 like the sender, the slot holds the
+        // sender's leaf
+        let account = verify_account(
+            STATE_ROOT.parse().unwrap(),
+            "0x0000000000000000000000000000000000000014"
+                .parse()
+                .unwrap(),
+            sender_proof().proof,
+        )
+        .unwrap();
+        assert!(account.is_none());
+    }
+
+    #[test]
+    pub fn test_verify_account_4_invalid() {
+        // an exclusion proof cannot stop above the empty slot
+        assert!(verify_account(
+            STATE_ROOT.parse().unwrap(),
+            "0x0000000000000000000000000000000000000014"
+                .parse()
+                .unwrap(),
+            parse_proof(&[ROOT_NODE]),
+        )
+        .is_err());
+        // nor can a present account be proven against another root
+        assert!(
+            verify_account(H256::zero(), sender_proof().address, sender_proof().proof).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_verify_account_status_1() {
+        let state_root = STATE_ROOT.parse().unwrap();
+        let sender = sender_proof();
+        assert_eq!(
+            verify_account_status(state_root, sender.address, sender.proof.clone()).unwrap(),
+            AccountStatus::Present(sender.account.clone())
+        );
+        // the exclusion proof of test_verify_account_3_absent_diverging_leaf
+        let absent = "0x0000000000000000000000000000000000000014"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            verify_account_status(state_root, absent, sender.proof).unwrap(),
+            AccountStatus::Absent
+        );
+
+        // geth drops empty accounts since EIP-161, so the one here is in a trie of
+        // its own
+        let (root, proof) = single_account(absent, &AccountData::empty());
+        assert_eq!(
+            verify_account_status(root, absent, proof.clone()).unwrap(),
+            AccountStatus::Empty
+        );
+        // ..which does not prove the address absent from another root
+        assert!(verify_account_status(state_root, absent, proof).is_err());
+
+        // a contract with neither nonce nor balance is not empty
+        let mut contract = contract_proof().account;
+        contract.nonce = U256::zero();
+        let (root, proof) = single_account(absent, &contract);
+        assert!(matches!(
+            verify_account_status(root, absent, proof).unwrap(),
+            AccountStatus::Present(_)
+        ));
+    }
+
+    // the contract proof as returned by eth_getProof for slots 1 and 2
+    fn contract_response() -> EIP1186ProofResponse {
+        let proof = contract_proof();
+        EIP1186ProofResponse {
+            address: proof.address,
+            balance: proof.account.balance,
+            code_hash: proof.account.code_hash,
+            nonce: proof.account.nonce.as_u64().into(),
+            storage_hash: proof.account.storage_root,
+            account_proof: proof.proof,
+            storage_proof: proof
+                .storage_proofs
+                .into_iter()
+                .map(|storage_proof| StorageProof {
+                    key: H256::from(pad32(storage_proof.slot)),
+                    value: storage_proof.value,
+                    proof: storage_proof.proof,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    pub fn test_verify_account_with_slots_1() {
+        let (account, slots) =
+            verify_account_with_slots(STATE_ROOT.parse().unwrap(), contract_response()).unwrap();
+        assert_eq!(account.nonce, U256::from(1));
+        assert_eq!(
+            slots,
+            vec![
+                (H256::from_low_u64_be(1), U256::from(2)),
+                (H256::from_low_u64_be(2), U256::from(4))
+            ]
+        );
+
+        // one of the slots tampered
+        let mut response = contract_response();
+        response.storage_proof[0].value = U256::from(3);
+        assert!(verify_account_with_slots(STATE_ROOT.parse().unwrap(), response).is_err());
+    }
+
+    #[test]
+    pub fn test_account_proof_verify_2_storage_proof_of_other_account() {
+        // valid proof of slot 1 of the contract, attached to the sender
+        let mut proof = sender_proof();
+        proof
+            .storage_proofs
+            .push(contract_proof().storage_proofs[0].clone());
+        assert!(proof.verify().is_err());
+    }
+
+    #[test]
+    pub fn test_account_proof_verify_3_wrong_state_root() {
+        let mut proof = contract_proof();
+        proof.state_root = H256::zero();
+        assert!(proof.verify().is_err());
+    }
+
+    #[test]
+    pub fn test_account_proof_verify_4_wrong_storage_value() {
+        let mut proof = contract_proof();
+        proof.storage_proofs[1].value = U256::from(5);
+        assert!(proof.verify().is_err());
+    }
+
+    #[test]
+    pub fn test_verified_account_try_from_1() {
+        let state_root: H256 = STATE_ROOT.parse().unwrap();
+        let response = contract_proof().to_eip1186().unwrap();
+        let verified: VerifiedAccount = (state_root, &response).try_into().unwrap();
+        assert_eq!(verified.state_root, STATE_ROOT.parse::<H256>().unwrap());
+        assert_eq!(verified.account.nonce, U256::from(1));
+        assert_eq!(
+            verified.storage,
+            vec![
+                (U256::from(1), U256::from(2)),
+                (U256::from(2), U256::from(4))
+            ]
+        );
+
+        // a storage value other than the proven one
+        let mut tampered = response.clone();
+        tampered.storage_proof[0].value = U256::from(3);
+        assert!(VerifiedAccount::try_from((state_root, tampered)).is_err());
+
+        // a flipped byte in the account leaf moves the root it hashes up to, the
+        // root node no longer points to it
+        let mut tampered = response.clone();
+        let mut leaf = tampered.account_proof[1].to_vec();
+        *leaf.last_mut().unwrap() ^= 1;
+        tampered.account_proof[1] = Bytes::from(leaf);
+        assert!(VerifiedAccount::try_from((state_root, tampered)).is_err());
+
+        // a response that is consistent on its own is still checked against the
+        // trusted root, a single leaf trie proves any account
+        let (forged_root, forged_proof) = single_account(response.address, &verified.account);
+        let mut forged = response.clone();
+        forged.account_proof = forged_proof;
+        forged.storage_proof.clear();
+        assert!(VerifiedAccount::try_from((forged_root, &forged)).is_ok());
+        assert!(VerifiedAccount::try_from((state_root, forged)).is_err());
+    }
+
+    // state trie holding only `account` at `address`, its root is the hash of the leaf
+    fn single_account(address: Address, account: &AccountData) -> (H256, Vec<Bytes>) {
+        let leaf = NodeData::Leaf {
+            key: Nibbles::from_address(address).unwrap(),
+            value: account.to_rlp(),
+        };
+        (leaf.hash().unwrap(), vec![leaf.to_raw_rlp().unwrap()])
+    }
+
+    #[test]
+    pub fn test_account_proof_to_eip1186_1_nonce_overflow() {
+        let mut proof = contract_proof();
+        proof.account.nonce = U256::from(u64::MAX);
+        assert_eq!(proof.to_eip1186().unwrap().nonce, u64::MAX.into());
+
+        proof.account.nonce += U256::one();
+        assert!(matches!(
+            proof.to_eip1186(),
+            Err(Error::NonceOverflow(nonce)) if nonce == proof.account.nonce
+        ));
+    }
+
+    #[test]
+    pub fn test_verify_account_transition_1() {
+        let sender = sender_proof();
+        let mut account = sender.account.clone();
+
+        // a transaction sent, paying for gas
+        account.nonce = U256::from(4);
+        account.balance -= U256::from(21000);
+        let (new_root, new_proof) = single_account(sender.address, &account);
+        let (old, new) = verify_account_transition(
+            sender.state_root,
+            new_root,
+            sender.address,
+            sender.proof.clone(),
+            new_proof,
+        )
+        .unwrap();
+        assert_eq!(old.unwrap().nonce, U256::from(3));
+        assert_eq!(new.unwrap().nonce, U256::from(4));
+
+        // the nonce went back, as after a reorg
+        account.nonce = U256::from(2);
+        let (new_root, new_proof) = single_account(sender.address, &account);
+        let result = verify_account_transition(
+            sender.state_root,
+            new_root,
+            sender.address,
+            sender.proof.clone(),
+            new_proof,
+        );
+        match result {
+            Err(Error::NonceDecreased(old, new)) => {
+                assert_eq!((old, new), (U256::from(3), U256::from(2)))
+            }
+            result => panic!("expected a decreased nonce, got {:?}", result),
+        }
+
+        // balance lost without a transaction
+        account.nonce = U256::from(3);
+        let (new_root, new_proof) = single_account(sender.address, &account);
+        let result = verify_account_transition(
+            sender.state_root,
+            new_root,
+            sender.address,
+            sender.proof.clone(),
+            new_proof.clone(),
+        );
+        assert!(matches!(result, Err(Error::BalanceDecreased(..))));
+        // the proofs are checked first
+        assert!(verify_account_transition(
+            sender.state_root,
+            new_root,
+            sender.address,
+            sender.proof,
+            vec![]
+        )
+        .is_err());
+
+        // a contract sends value without a transaction
+        let contract = contract_proof();
+        let mut account = contract.account.clone();
+        assert_ne!(account.code_hash, EMPTY_CODE_HASH);
+        account.balance = U256::from(5);
+        let (old_root, old_proof) = single_account(contract.address, &account);
+        account.balance = U256::from(1);
+        let (new_root, new_proof) = single_account(contract.address, &account);
+        verify_account_transition(old_root, new_root, contract.address, old_proof, new_proof)
+            .unwrap();
+
+        let mut decreased = contract.account.clone();
+        decreased.nonce = U256::zero();
+        assert!(verify_nonce_monotonic(&contract.account, &decreased).is_err());
+        assert!(verify_nonce_monotonic(&decreased, &contract.account).is_ok());
+    }
+}
@@ -1,10 +1,20 @@
-use super::utils::{Nibbles, Trie};
-use crate::error::Error;
+use super::utils::{
+    account_key, bytes_to_h256, check_canonical, empty_root, KeyStatus, LeafKind, LoadLimits,
+    Nibbles, PathStatus, Trie, TrieStats, WitnessSize,
+};
+use crate::error::{DecodeContext, Error};
 use ethers::{
     prelude::EthDisplay,
     types::{Address, Bytes, H256, U256},
     utils::rlp::{Rlp, RlpStream},
 };
+use serde::Serialize;
+
+/// Code hash of accounts without code, keccak256 of the empty string.
+pub const EMPTY_CODE_HASH: H256 = H256([
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+]);
 
 #[derive(Debug, Clone, EthDisplay, PartialEq)]
 pub struct AccountTrie(Trie);
@@ -18,18 +28,77 @@ impl AccountTrie {
         AccountTrie(Trie::from_root(root))
     }
 
+    /// Same as `from_root`, with the leaves of loaded proofs checked to be of
+    /// `expect`. `LeafKind::Raw` checks nothing.
+    pub fn from_root_expecting(root: H256, expect: LeafKind) -> Self {
+        let mut trie = Trie::from_root(root);
+        trie.set_leaf_kind(expect);
+        AccountTrie(trie)
+    }
+
     pub fn set_root(&mut self, root: H256) -> Result<(), Error> {
         self.0.set_root(root)
     }
 
+    pub fn reset(&mut self, root: H256) {
+        self.0.reset(root)
+    }
+
     pub fn root(&self) -> Option<H256> {
         self.0.root
     }
 
+    pub fn witness_size(&self) -> WitnessSize {
+        self.0.witness_size()
+    }
+
+    pub fn stats(&self) -> TrieStats {
+        self.0.stats()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.0.node_count()
+    }
+
+    pub fn set_load_limits(&mut self, limits: LoadLimits) {
+        self.0.set_load_limits(limits);
+    }
+
+    pub fn checkpoint(&mut self) -> usize {
+        self.0.checkpoint()
+    }
+
+    pub fn revert_to_checkpoint(&mut self, checkpoint: usize) -> Result<(), Error> {
+        self.0.revert_to_checkpoint(checkpoint)
+    }
+
+    pub fn discard_journal(&mut self) {
+        self.0.discard_journal()
+    }
+
     pub fn get_account_data(&self, address: Address) -> Result<AccountData, Error> {
         let path = Nibbles::from_address(address)?;
         let raw_account = self.0.get_value(path)?;
-        AccountData::from_raw_rlp(raw_account)
+        AccountData::from_rlp(raw_account)
+    }
+
+    // Absent only when the loaded nodes prove that the account does not exist
+    pub fn contains(&self, address: Address) -> Result<KeyStatus, Error> {
+        self.0.contains(Nibbles::from_address(address)?)
+    }
+
+    pub fn is_path_loaded(&self, address: Address) -> Result<PathStatus, Error> {
+        self.0.is_path_loaded(Nibbles::from_address(address)?)
+    }
+
+    // storage root of a loaded account, to load its storage proofs on a StorageTrie
+    pub fn storage_root_for(&self, address: Address) -> Result<H256, Error> {
+        Ok(self.get_account_data(address)?.storage_root)
+    }
+
+    // an EOA has the empty code hash, errors if the account is not loaded
+    pub fn account_is_contract(&self, address: Address) -> Result<bool, Error> {
+        Ok(self.get_account_data(address)?.code_hash != EMPTY_CODE_HASH)
     }
 
     pub fn set_account_data(
@@ -38,28 +107,61 @@ impl AccountTrie {
         new_value: AccountData,
     ) -> Result<(), Error> {
         let path = Nibbles::from_address(address)?;
-        self.0.set_value(path, new_value.to_raw_rlp()?)
+        self.0.set_value(path, new_value.to_rlp())
     }
 
     pub fn set_nonce(&mut self, address: Address, new_nonce: U256) -> Result<(), Error> {
         let mut data = self.get_account_data(address)?;
         data.nonce = new_nonce;
         let path = Nibbles::from_address(address)?;
-        self.0.set_value(path, data.to_raw_rlp()?)
+        self.0.set_value(path, data.to_rlp())
     }
 
     pub fn set_balance(&mut self, address: Address, new_balance: U256) -> Result<(), Error> {
         let mut data = self.get_account_data(address)?;
         data.balance = new_balance;
         let path = Nibbles::from_address(address)?;
-        self.0.set_value(path, data.to_raw_rlp()?)
+        self.0.set_value(path, data.to_rlp())
+    }
+
+    pub fn add_balance(&mut self, address: Address, amount: U256) -> Result<(), Error> {
+        let mut data = self.get_account_data(address)?;
+        data.balance = data
+            .balance
+            .checked_add(amount)
+            .ok_or(Error::BalanceOverflow(data.balance, amount))?;
+        let path = Nibbles::from_address(address)?;
+        self.0.set_value(path, data.to_rlp())
     }
 
     pub fn set_code_hash(&mut self, address: Address, new_code_hash: H256) -> Result<(), Error> {
         let mut data = self.get_account_data(address)?;
         data.code_hash = new_code_hash;
         let path = Nibbles::from_address(address)?;
-        self.0.set_value(path, data.to_raw_rlp()?)
+        self.0.set_value(path, data.to_rlp())
+    }
+
+    // removes the account leaf, returns the data it held if the account existed
+    pub fn delete_account(&mut self, address: Address) -> Result<Option<AccountData>, Error> {
+        let path = Nibbles::from_address(address)?;
+        self.0
+            .remove_value(path)?
+            .map(AccountData::from_rlp)
+            .transpose()
+    }
+
+    /// Accounts whose full path is loaded, keyed by hashed address in order. Leaves
+    /// that do not decode to an account are left out, `get_account_data` errors on
+    /// them.
+    pub fn entries(&self) -> impl Iterator<Item = (H256, AccountData)> {
+        self.0.leaves().into_iter().filter_map(|(key, value)| {
+            Some((bytes_to_h256(key).ok()?, AccountData::from_rlp(value).ok()?))
+        })
+    }
+
+    // proof of the account at the current root, see Trie::prove
+    pub fn prove(&self, address: Address) -> Result<Vec<Bytes>, Error> {
+        self.0.prove(Nibbles::from_address(address)?)
     }
 
     pub fn load_proof(
@@ -69,11 +171,35 @@ impl AccountTrie {
         proof: Vec<Bytes>,
     ) -> Result<(), Error> {
         let path = Nibbles::from_address(address)?;
-        self.0.load_proof(path, value.to_raw_rlp()?, proof)
+        self.0.load_proof(path, value.to_rlp(), proof)
+    }
+
+    // loads a proof that the account does not exist
+    pub fn load_absence_proof(&mut self, address: Address, proof: Vec<Bytes>) -> Result<(), Error> {
+        let path = Nibbles::from_address(address)?;
+        self.0.load_proof(path, Bytes::new(), proof)
+    }
+}
+
+/// Key of a loaded account, the address when its preimage is known and the
+/// keccak256 of it otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum AccountKey {
+    Address(Address),
+    Hashed(H256),
+}
+
+impl AccountKey {
+    // the key of the account in the state trie
+    pub fn hashed(&self) -> H256 {
+        match self {
+            AccountKey::Address(address) => account_key(*address),
+            AccountKey::Hashed(hash) => *hash,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AccountData {
     pub nonce: U256,
     pub balance: U256,
@@ -82,30 +208,64 @@ pub struct AccountData {
 }
 
 impl AccountData {
+    // state of an account that does not exist yet
+    pub fn empty() -> Self {
+        Self {
+            nonce: U256::zero(),
+            balance: U256::zero(),
+            storage_root: empty_root(),
+            code_hash: EMPTY_CODE_HASH,
+        }
+    }
+
+    // empty as EIP-161 has it, without code and with zero nonce and balance
+    pub fn is_empty(&self) -> bool {
+        self.nonce.is_zero() && self.balance.is_zero() && self.code_hash == EMPTY_CODE_HASH
+    }
+
+    /// Decodes an account leaf value as long as its four items decode, see
+    /// `from_rlp` to also require the canonical encoding.
     pub fn from_raw_rlp(raw: Bytes) -> Result<Self, Error> {
         let rlp = Rlp::new(&raw);
         Ok(Self {
-            nonce: rlp.val_at(0)?,
-            balance: rlp.val_at(1)?,
-            storage_root: rlp.val_at(2)?,
-            code_hash: rlp.val_at(3)?,
+            nonce: rlp.val_at(0).decoding("account nonce")?,
+            balance: rlp.val_at(1).decoding("account balance")?,
+            storage_root: rlp.val_at(2).decoding("account storage root")?,
+            code_hash: rlp.val_at(3).decoding("account code hash")?,
         })
     }
 
-    pub fn to_raw_rlp(&self) -> Result<Bytes, Error> {
+    /// Decodes an account leaf value that is byte for byte the one `to_rlp` gives,
+    /// else errors with `Error::NonCanonicalRlp` at the first difference: leading
+    /// zeros, a zero encoded as `0x00`, a redundant prefix or items past the four.
+    pub fn from_rlp(raw: Bytes) -> Result<Self, Error> {
+        let account = Self::from_raw_rlp(raw.clone())?;
+        check_canonical(&raw, &account.to_rlp())?;
+        Ok(account)
+    }
+
+    /// RLP of `[nonce, balance, storageRoot, codeHash]` as it goes in the account
+    /// leaf: the integers on their minimal big endian bytes (zero is the empty
+    /// string) and the hashes on 32 bytes. The state root of any account written
+    /// to the trie depends on this encoding.
+    pub fn to_rlp(&self) -> Bytes {
         let mut rlp_stream = RlpStream::new();
         rlp_stream.begin_list(4);
         rlp_stream.append(&self.nonce);
         rlp_stream.append(&self.balance);
         rlp_stream.append(&self.storage_root);
         rlp_stream.append(&self.code_hash);
-        Ok(Bytes::from(rlp_stream.out().to_vec()))
+        Bytes::from(rlp_stream.out().to_vec())
+    }
+
+    pub fn to_raw_rlp(&self) -> Result<Bytes, Error> {
+        Ok(self.to_rlp())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{AccountData, AccountTrie, Address, U256};
+    use super::{AccountData, AccountTrie, Address, Bytes, Error, LeafKind, EMPTY_CODE_HASH, U256};
     use ethers::utils::parse_ether;
     use ethers_core::utils::hex;
 
@@ -132,6 +292,229 @@ mod tests {
         println!("{:?}", account);
     }
 
+    // RLP of the empty root and empty code hash, the end of the value of an EOA leaf
+    const EMPTY: &str = "a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a0c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470";
+
+    #[test]
+    pub fn test_account_data_rlp_1_mainnet() {
+        // account leaf values of the sender, receiver and miner of block 1000008,
+        // see test_account_trie_updates_1
+        for (nonce, balance, raw_rlp) in [
+            (
+                "0x2a127",
+                "0xb5248f2ebf8f5db4ef",
+                "f8508302a12789b5248f2ebf8f5db4ef",
+            ),
+            ("0x20d", "0x175a0778", "f84a82020d84175a0778"),
+            (
+                "0x2651",
+                "0x43cc248fcad9b3f3b0",
+                "f84f8226518943cc248fcad9b3f3b0",
+            ),
+        ] {
+            let raw_rlp: Bytes = format!("0x{}{}", raw_rlp, EMPTY).parse().unwrap();
+            let account = AccountData::from_rlp(raw_rlp.clone()).unwrap();
+            assert_eq!(
+                account,
+                AccountData {
+                    nonce: U256::from(nonce),
+                    balance: U256::from(balance),
+                    ..AccountData::empty()
+                }
+            );
+            assert_eq!(account.to_rlp(), raw_rlp);
+        }
+
+        // zero nonce and balance are empty strings
+        assert_eq!(
+            hex::encode(AccountData::empty().to_rlp()),
+            format!("f8448080{}", EMPTY)
+        );
+    }
+
+    #[test]
+    pub fn test_account_data_rlp_2_non_canonical() {
+        let parse = |raw: String| -> Bytes { raw.parse().unwrap() };
+
+        // a fifth item, which the lenient decoding ignores
+        let raw = parse(format!("0xf8458080{}01", EMPTY));
+        assert!(AccountData::from_raw_rlp(raw.clone()).is_ok());
+        assert!(matches!(
+            AccountData::from_rlp(raw),
+            Err(Error::NonCanonicalRlp(1))
+        ));
+
+        let storage_root = "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
+        for raw in [
+            // a zero nonce as 0x00
+            format!("0xf8440080{}", EMPTY),
+            // the balance 0x0100 with a leading zero
+            format!("0xf8478083000100{}", EMPTY),
+            // a nonce of 5 behind a string prefix
+            format!("0xf845810580{}", EMPTY),
+            // a storage root on 31 bytes
+            format!("0xf84380809f{}{}", &storage_root[2..], &EMPTY[66..]),
+        ] {
+            assert!(AccountData::from_rlp(parse(raw)).is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_account_trie_storage_root_for_1() {
+        // contract deployed on geth --dev, see state_trie tests
+        let mut trie = AccountTrie::new();
+        let contract = "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+            .parse::<Address>()
+            .unwrap();
+
+        trie.load_proof(
+            contract,
+            AccountData {
+                nonce: U256::from(1),
+                balance: U256::zero(),
+                storage_root: "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+                    .parse()
+                    .unwrap(),
+                code_hash: "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16"
+                    .parse()
+                    .unwrap(),
+            },
+            vec![
+                "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080".parse().unwrap(),
+                "0xf869a03a7a2ee9b4f54ecbf2e04737a19215c0864d20c9a332db61d093e9ec95b2e87ab846f8440180a029cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748ca02e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16".parse().unwrap(),
+            ],
+        )
+        .unwrap();
+
+        // storageHash returned by eth_getProof
+        assert_eq!(
+            hex::encode(trie.storage_root_for(contract).unwrap()),
+            "29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+        );
+
+        // tx sender is under a branch child that was not loaded
+        assert!(trie
+            .storage_root_for(
+                "0x3736b9d9d35d8c4f41d98a412fe9211024453575"
+                    .parse()
+                    .unwrap()
+            )
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_account_trie_leaf_kind_1() {
+        // contract deployed on geth --dev, see state_trie tests
+        let contract = "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+            .parse::<Address>()
+            .unwrap();
+        let account = AccountData {
+            nonce: U256::from(1),
+            balance: U256::zero(),
+            storage_root: "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+                .parse()
+                .unwrap(),
+            code_hash: "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16"
+                .parse()
+                .unwrap(),
+        };
+
+        let mut trie = AccountTrie::from_root_expecting(
+            "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909"
+                .parse()
+                .unwrap(),
+            LeafKind::Account,
+        );
+        trie.load_proof(
+            contract,
+            account.clone(),
+            vec![
+                "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080".parse().unwrap(),
+                "0xf869a03a7a2ee9b4f54ecbf2e04737a19215c0864d20c9a332db61d093e9ec95b2e87ab846f8440180a029cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748ca02e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16".parse().unwrap(),
+            ],
+        )
+        .unwrap();
+
+        // storage proof of slot 1 of the contract, against its storage root
+        let mut trie = AccountTrie::from_root_expecting(account.storage_root, LeafKind::Account);
+        let result = trie.load_proof(
+            contract,
+            account,
+            vec![
+                "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc5808080808080a0236e8f61ecde6abfebc6c529441f782f62469d8a2cc47b7aace2c136bd3b1ff08080808080".parse().unwrap(),
+                "0xe2a0310e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf602".parse().unwrap(),
+            ],
+        );
+        match result {
+            Err(Error::UnexpectedLeafShape(expected, value)) => {
+                assert_eq!(expected, LeafKind::Account);
+                assert_eq!(value, "0x02".parse::<Bytes>().unwrap());
+            }
+            result => panic!("expected an unexpected leaf shape, got {:?}", result),
+        }
+    }
+
+    #[test]
+    pub fn test_account_trie_is_contract_1() {
+        // contract and tx sender from geth --dev, see state_trie tests
+        let mut trie = AccountTrie::new();
+        let contract = "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+            .parse::<Address>()
+            .unwrap();
+        let sender = "0x3736b9d9d35d8c4f41d98a412fe9211024453575"
+            .parse::<Address>()
+            .unwrap();
+        let branch = "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080";
+
+        trie.load_proof(
+            contract,
+            AccountData {
+                nonce: U256::from(1),
+                balance: U256::zero(),
+                storage_root: "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+                    .parse()
+                    .unwrap(),
+                code_hash: "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16"
+                    .parse()
+                    .unwrap(),
+            },
+            vec![
+                branch.parse().unwrap(),
+                "0xf869a03a7a2ee9b4f54ecbf2e04737a19215c0864d20c9a332db61d093e9ec95b2e87ab846f8440180a029cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748ca02e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16".parse().unwrap(),
+            ],
+        )
+        .unwrap();
+        trie.load_proof(
+            sender,
+            AccountData {
+                nonce: U256::from(3),
+                balance: "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82"
+                    .parse()
+                    .unwrap(),
+                storage_root: "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+                    .parse()
+                    .unwrap(),
+                code_hash: EMPTY_CODE_HASH,
+            },
+            vec![
+                branch.parse().unwrap(),
+                "0xf889a03e19976962fea3751225213669050369b7cd26650bc43815007705e945b5aa57b866f86403a0ffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a0c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470".parse().unwrap(),
+            ],
+        )
+        .unwrap();
+
+        assert!(trie.account_is_contract(contract).unwrap());
+        assert!(!trie.account_is_contract(sender).unwrap());
+        // miner of block 1000008 on mainnet, not part of this trie
+        assert!(trie
+            .account_is_contract(
+                "0x68795C4AA09D6f4Ed3E5DeDDf8c2AD3049A601da"
+                    .parse()
+                    .unwrap()
+            )
+            .is_err());
+    }
+
     #[test]
     pub fn test_account_trie_updates_1() {
         // This test uses block 1000008 on ethereum mainnet which just has 1 simple ether transfer tx
@@ -238,3 +621,42 @@ mod tests {
         );
     }
 }
+
+#[cfg(all(test, feature = "test-utils"))]
+mod proptests {
+    use proptest::{collection::vec, prelude::*};
+
+    use super::{AccountData, Bytes, H256, U256};
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(512))]
+
+        #[test]
+        fn test_account_data_rlp_round_trip(
+            nonce in any::<u64>(),
+            balance in any::<[u8; 32]>(),
+            // most balances are far from 32 bytes, down to zero
+            zeros in 0usize..=32,
+            storage_root in any::<[u8; 32]>(),
+            code_hash in any::<[u8; 32]>(),
+        ) {
+            let mut balance = balance;
+            balance[..zeros].fill(0);
+            let account = AccountData {
+                nonce: U256::from(nonce),
+                balance: U256::from_big_endian(&balance),
+                storage_root: H256(storage_root),
+                code_hash: H256(code_hash),
+            };
+            prop_assert_eq!(AccountData::from_rlp(account.to_rlp()).unwrap(), account);
+        }
+
+        #[test]
+        fn test_account_data_from_rlp_only_canonical(raw in vec(any::<u8>(), 0..120)) {
+            let raw = Bytes::from(raw);
+            if let Ok(account) = AccountData::from_rlp(raw.clone()) {
+                prop_assert_eq!(account.to_rlp(), raw);
+            }
+        }
+    }
+}
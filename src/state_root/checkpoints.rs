@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+use ethers::types::H256;
+
+/// Trusted `(block number, state root)` pairs, e.g. taken from headers checked
+/// out of band, for `StateTrie::new_checked` to only accept state roots among
+/// them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Checkpoints(BTreeMap<u64, H256>);
+
+impl Checkpoints {
+    pub fn new() -> Self {
+        Checkpoints(BTreeMap::new())
+    }
+
+    /// Pins `root` as the state root of `block`, returning the root it replaces.
+    pub fn insert(&mut self, block: u64, root: H256) -> Option<H256> {
+        self.0.insert(block, root)
+    }
+
+    pub fn get(&self, block: u64) -> Option<H256> {
+        self.0.get(&block).copied()
+    }
+
+    // the lowest block pinned to `root`, distinct blocks can share a state root
+    pub fn block_of(&self, root: H256) -> Option<u64> {
+        self.0
+            .iter()
+            .find(|(_, pinned)| **pinned == root)
+            .map(|(block, _)| *block)
+    }
+
+    pub fn contains_root(&self, root: H256) -> bool {
+        self.block_of(root).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromIterator<(u64, H256)> for Checkpoints {
+    fn from_iter<I: IntoIterator<Item = (u64, H256)>>(iter: I) -> Self {
+        Checkpoints(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checkpoints, H256};
+    use crate::{error::Error, state_root::state_trie::StateTrie};
+
+    #[test]
+    pub fn test_checkpoints_new_checked_1() {
+        // state roots of the sepolia and holesky genesis blocks
+        let sepolia: H256 = "0x5eb6e371a698b8d68f665192350ffcecbbbf322916f4b51bd79bb6887da3f494"
+            .parse()
+            .unwrap();
+        let holesky: H256 = "0x69d8c9d72f6fa4ad42d4702b433707212f90db395eb54dc20bc85de253788783"
+            .parse()
+            .unwrap();
+        let mut checkpoints = Checkpoints::new();
+        assert_eq!(checkpoints.insert(0, sepolia), None);
+        assert_eq!(checkpoints.insert(1, holesky), None);
+        assert_eq!(checkpoints.block_of(holesky), Some(1));
+        assert_eq!(checkpoints.get(0), Some(sepolia));
+
+        let trie = StateTrie::new_checked(sepolia, &checkpoints).unwrap();
+        assert_eq!(trie.root(), Some(sepolia));
+
+        let unknown = H256::repeat_byte(0x11);
+        match StateTrie::new_checked(unknown, &checkpoints) {
+            Err(Error::UntrustedRoot(root)) => assert_eq!(root, unknown),
+            result => panic!("expected an untrusted root, got {:?}", result),
+        }
+        // a root no longer pinned is not trusted anymore
+        assert_eq!(checkpoints.insert(1, unknown), Some(holesky));
+        assert!(StateTrie::new_checked(holesky, &checkpoints).is_err());
+        assert!(StateTrie::new_checked(unknown, &checkpoints).is_ok());
+    }
+}
@@ -0,0 +1,322 @@
+use std::time::Duration;
+
+use ethers::{
+    providers::{JsonRpcClient, Middleware, Provider, ProviderError, RpcError},
+    types::{Address, BlockId, EIP1186ProofResponse, H256},
+};
+use futures::{stream, StreamExt};
+
+use super::state_trie::StateTrie;
+use crate::error::Error;
+
+/// Limits on the requests made by `fetch_proofs`.
+#[derive(Clone, Debug)]
+pub struct FetchConfig {
+    // requests in flight at once
+    pub max_concurrency: usize,
+    // attempts after the first one, only for errors that can go away
+    pub max_retries: u32,
+    // wait before the first retry, doubled for every retry after it
+    pub initial_backoff: Duration,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            max_concurrency: 8,
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Fetches `eth_getProof` for every `(address, slots)` target at `block` and loads
+/// the proofs into `trie`, which has to be at the state root of `block` (see
+/// `StateTrie::for_block`) for the proofs to verify.
+///
+/// Returns the outcome of each target in the order given. A target that fails to
+/// fetch or verify does not stop the others.
+pub async fn fetch_proofs<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    block: BlockId,
+    targets: Vec<(Address, Vec<H256>)>,
+    config: &FetchConfig,
+    trie: &mut StateTrie,
+) -> Vec<(Address, Result<(), Error>)> {
+    let responses = stream::iter(targets)
+        .map(|(address, slots)| async move {
+            let response = fetch_proof(provider, block, address, slots, config).await;
+            (address, response)
+        })
+        .buffered(config.max_concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    // loading takes the trie exclusively, so it waits for all responses
+    responses
+        .into_iter()
+        .map(|(address, response)| {
+            let result = response.and_then(|proof| {
+                if proof.address != address {
+                    return Err(Error::ProofAddressMismatch(address, proof.address));
+                }
+                trie.load_proof(proof)
+            });
+            (address, result)
+        })
+        .collect()
+}
+
+async fn fetch_proof<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    block: BlockId,
+    address: Address,
+    slots: Vec<H256>,
+    config: &FetchConfig,
+) -> Result<EIP1186ProofResponse, Error> {
+    let mut backoff = config.initial_backoff;
+    let mut retries = 0;
+    loop {
+        match provider
+            .get_proof(address, slots.clone(), Some(block))
+            .await
+        {
+            Ok(proof) => return Ok(proof),
+            Err(err) if retries < config.max_retries && is_retryable(&err) => {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                retries += 1;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+fn is_retryable(err: &ProviderError) -> bool {
+    match err {
+        ProviderError::JsonRpcClientError(err) => {
+            match (err.as_error_response(), err.as_serde_error()) {
+                // the node answered, asking again only helps if it asked to slow down
+                (Some(response), _) => {
+                    response.code == 429
+                        || response.code == -32005
+                        || is_rate_limit(&response.message)
+                }
+                // a body that is not JSON-RPC comes back the same when asked again,
+                // unless it is the plain text a proxy answers HTTP 429 with
+                (None, Some(_)) => is_rate_limit(&err.to_string()),
+                // the request did not get an answer, such as a connection that was
+                // refused or reset
+                (None, None) => true,
+            }
+        }
+        ProviderError::HTTPError(_) => true,
+        _ => false,
+    }
+}
+
+fn is_rate_limit(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("rate limit") || message.contains("too many requests")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fmt::Debug,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use ethers::{
+        providers::{JsonRpcClient, JsonRpcError, MockError, Provider},
+        types::{Address, EIP1186ProofResponse, H256},
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use super::{fetch_proofs, FetchConfig};
+    use crate::{error::Error, state_root::state_trie::StateTrie};
+
+    const STATE_ROOT: &str = "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909";
+
+    // tx sender of test_state_1, from the eth_getProof fixtures
+    fn sender_proof() -> EIP1186ProofResponse {
+        let response: serde_json::Value =
+            serde_json::from_str(include_str!("../../testdata/eth_getProof/sender.json")).unwrap();
+        serde_json::from_value(response["result"].clone()).unwrap()
+    }
+
+    // answers eth_getProof from fixtures, failing the first calls per address on
+    // the transport or with a rate limit, and answering with json that does not
+    // parse for the malformed ones
+    #[derive(Debug, Default)]
+    struct MockClient {
+        proofs: HashMap<Address, EIP1186ProofResponse>,
+        unreachable: HashMap<Address, usize>,
+        rate_limited: HashMap<Address, usize>,
+        malformed: Vec<Address>,
+        calls: Mutex<HashMap<Address, usize>>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    fn rpc_error(code: i64, message: &str) -> MockError {
+        MockError::JsonRpcError(JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for MockClient {
+        type Error = MockError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, MockError>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            assert_eq!(method, "eth_getProof");
+            let params = serde_json::to_value(params).map_err(MockError::SerdeJson)?;
+            let address: Address =
+                serde_json::from_value(params[0].clone()).map_err(MockError::SerdeJson)?;
+            let call = {
+                let mut calls = self.calls.lock().unwrap();
+                let call = calls.entry(address).or_default();
+                *call += 1;
+                *call
+            };
+
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            // no answer came back, as when the connection is reset
+            if call <= self.unreachable.get(&address).copied().unwrap_or_default() {
+                return Err(MockError::EmptyResponses);
+            }
+            if call <= self.rate_limited.get(&address).copied().unwrap_or_default() {
+                return Err(rpc_error(429, "rate limited"));
+            }
+            if self.malformed.contains(&address) {
+                let err = serde_json::from_str::<EIP1186ProofResponse>("{").unwrap_err();
+                return Err(MockError::SerdeJson(err));
+            }
+            match self.proofs.get(&address) {
+                Some(proof) => {
+                    let proof = serde_json::to_value(proof).map_err(MockError::SerdeJson)?;
+                    serde_json::from_value(proof).map_err(MockError::SerdeJson)
+                }
+                None => Err(rpc_error(-32000, "missing trie node")),
+            }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_proofs_1() {
+        let sender = sender_proof().address;
+        let unknown = (1..=3).map(Address::from_low_u64_be).collect::<Vec<_>>();
+        let provider = Provider::new(MockClient {
+            proofs: HashMap::from([(sender, sender_proof())]),
+            rate_limited: HashMap::from([(sender, 2)]),
+            ..Default::default()
+        });
+        let config = FetchConfig {
+            max_concurrency: 2,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+        };
+
+        let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+        let targets = std::iter::once(sender)
+            .chain(unknown.iter().copied())
+            .map(|address| (address, Vec::<H256>::new()))
+            .collect();
+        let results = fetch_proofs(&provider, 2u64.into(), targets, &config, &mut trie).await;
+
+        // the sender made it through the rate limit, the others failed on their own
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].0, sender);
+        assert!(results[0].1.is_ok());
+        for (result, address) in results[1..].iter().zip(unknown.iter()) {
+            assert_eq!(result.0, *address);
+            assert!(matches!(result.1, Err(Error::ProviderError(_))));
+        }
+        assert_eq!(
+            trie.account_trie.get_account_data(sender).unwrap().nonce,
+            3.into()
+        );
+
+        let client = provider.as_ref();
+        assert_eq!(client.max_in_flight.load(Ordering::SeqCst), 2);
+        let calls = client.calls.lock().unwrap();
+        assert_eq!(calls[&sender], 3);
+        // an error that does not go away is not retried
+        assert!(unknown.iter().all(|address| calls[address] == 1));
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_proofs_2_malformed_response() {
+        let sender = sender_proof().address;
+        let provider = Provider::new(MockClient {
+            proofs: HashMap::from([(sender, sender_proof())]),
+            malformed: vec![sender],
+            ..Default::default()
+        });
+        let config = FetchConfig {
+            max_concurrency: 1,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+        };
+
+        let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+        let results = fetch_proofs(
+            &provider,
+            2u64.into(),
+            vec![(sender, Vec::new())],
+            &config,
+            &mut trie,
+        )
+        .await;
+
+        // a response that does not parse comes back the same when asked again
+        assert!(matches!(results[0].1, Err(Error::ProviderError(_))));
+        assert_eq!(provider.as_ref().calls.lock().unwrap()[&sender], 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_fetch_proofs_3_transport_error() {
+        let sender = sender_proof().address;
+        let provider = Provider::new(MockClient {
+            proofs: HashMap::from([(sender, sender_proof())]),
+            unreachable: HashMap::from([(sender, 2)]),
+            ..Default::default()
+        });
+        let config = FetchConfig {
+            max_concurrency: 1,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+        };
+
+        let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+        let results = fetch_proofs(
+            &provider,
+            2u64.into(),
+            vec![(sender, Vec::new())],
+            &config,
+            &mut trie,
+        )
+        .await;
+
+        // both failures of the transport were retried
+        assert!(results[0].1.is_ok());
+        assert_eq!(provider.as_ref().calls.lock().unwrap()[&sender], 3);
+    }
+}
@@ -1,4 +1,15 @@
+pub mod account_proof;
 pub mod account_trie;
+pub mod checkpoints;
+#[cfg(feature = "provider")]
+pub mod fetcher;
+#[cfg(feature = "provider")]
+pub mod prestate;
+pub mod proof_batch;
+pub mod receipts_trie;
 pub mod state_trie;
 pub mod storage_trie;
 pub mod utils;
+pub mod withdrawals_trie;
+#[cfg(feature = "zkevm")]
+pub mod zkevm;
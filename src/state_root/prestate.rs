@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethers::{
+    providers::{JsonRpcClient, Provider},
+    types::{Address, BigEndianHash, BlockId, Bytes, H256, U256},
+    utils::keccak256,
+};
+use serde::Deserialize;
+
+use super::{
+    account_trie::{AccountData, EMPTY_CODE_HASH},
+    fetcher::{fetch_proofs, FetchConfig},
+    state_trie::{AccountUpdate, StateTrie},
+    utils::KeyStatus,
+};
+use crate::error::Error;
+
+/// Account as the `prestateTracer` of `debug_traceTransaction` reports it. The
+/// tracer leaves out fields that are zero or empty.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct PrestateAccount {
+    pub balance: Option<U256>,
+    pub nonce: Option<u64>,
+    pub code: Option<Bytes>,
+    #[serde(default)]
+    pub storage: BTreeMap<H256, H256>,
+}
+
+/// Output of the `prestateTracer`, every account and slot the transaction touches
+/// with its value before the transaction.
+pub type Prestate = BTreeMap<Address, PrestateAccount>;
+
+/// Output of the `prestateTracer` with `diffMode`. `post` only holds what the
+/// transaction changed: an account only in `pre` is destructed, and a slot in
+/// `pre` that its `post` account does not have is cleared.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct PrestateDiff {
+    pub pre: Prestate,
+    pub post: Prestate,
+}
+
+/// A value the tracer reports before the transaction that is not the proven one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PrestateMismatch {
+    // (address, traced, proven) for the fields of an account
+    Balance(Address, U256, U256),
+    Nonce(Address, U256, U256),
+    CodeHash(Address, H256, H256),
+    // (address, slot, traced, proven)
+    Storage(Address, H256, U256, U256),
+}
+
+/// Parses the tracer output, either the result object or the whole JSON-RPC
+/// response.
+pub fn parse_prestate(json: &str) -> Result<Prestate, Error> {
+    Ok(serde_json::from_value(tracer_result(json)?)?)
+}
+
+/// Same as `parse_prestate` for the output of `diffMode`.
+pub fn parse_prestate_diff(json: &str) -> Result<PrestateDiff, Error> {
+    Ok(serde_json::from_value(tracer_result(json)?)?)
+}
+
+fn tracer_result(json: &str) -> Result<serde_json::Value, Error> {
+    let mut json: serde_json::Value = serde_json::from_str(json)?;
+    if let Some(result) = json.get_mut("result").map(serde_json::Value::take) {
+        json = result;
+    }
+    Ok(json)
+}
+
+/// Every account of `prestate` along with its slots, as `fetch_proofs` takes them.
+pub fn proof_targets(prestate: &Prestate) -> Vec<(Address, Vec<H256>)> {
+    prestate
+        .iter()
+        .map(|(address, account)| (*address, account.storage.keys().copied().collect()))
+        .collect()
+}
+
+/// Compares every value of `prestate` with the one proven in `trie`, which needs
+/// the proofs of all of them loaded. Fields the tracer leaves out are taken as
+/// zero or empty.
+pub fn check_prestate(
+    trie: &StateTrie,
+    prestate: &Prestate,
+) -> Result<Vec<PrestateMismatch>, Error> {
+    let mut mismatches = Vec::new();
+    for (address, account) in prestate {
+        let address = *address;
+        let proven = match trie.account_trie.contains(address)? {
+            KeyStatus::Present(raw) => AccountData::from_rlp(raw)?,
+            KeyStatus::Absent => AccountData::empty(),
+            KeyStatus::Unknown { missing_hash, .. } => {
+                return Err(Error::MissingTrieNode(missing_hash))
+            }
+        };
+
+        let balance = account.balance.unwrap_or_default();
+        if balance != proven.balance {
+            mismatches.push(PrestateMismatch::Balance(address, balance, proven.balance));
+        }
+        let nonce = U256::from(account.nonce.unwrap_or_default());
+        if nonce != proven.nonce {
+            mismatches.push(PrestateMismatch::Nonce(address, nonce, proven.nonce));
+        }
+        let code_hash = account.code.as_ref().map_or(EMPTY_CODE_HASH, code_hash);
+        if code_hash != proven.code_hash {
+            mismatches.push(PrestateMismatch::CodeHash(
+                address,
+                code_hash,
+                proven.code_hash,
+            ));
+        }
+
+        let storage_trie = trie.get_storage_trie(proven.storage_root);
+        for (slot, value) in &account.storage {
+            let value = value.into_uint();
+            let proven_value = storage_trie.get_value(slot.into_uint())?;
+            if value != proven_value {
+                mismatches.push(PrestateMismatch::Storage(
+                    address,
+                    *slot,
+                    value,
+                    proven_value,
+                ));
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+impl PrestateDiff {
+    /// Changes of the transaction for `StateTrie::apply_updates`, in address order.
+    pub fn account_updates(&self) -> Vec<AccountUpdate> {
+        let addresses = self
+            .pre
+            .keys()
+            .chain(self.post.keys())
+            .collect::<BTreeSet<_>>();
+        addresses
+            .into_iter()
+            .map(|address| match self.post.get(address) {
+                None => AccountUpdate {
+                    address: *address,
+                    deleted: true,
+                    ..Default::default()
+                },
+                Some(post) => {
+                    // slots of pre that post does not have are cleared
+                    let mut storage = self
+                        .pre
+                        .get(address)
+                        .map(|pre| {
+                            pre.storage
+                                .keys()
+                                .map(|slot| (*slot, H256::zero()))
+                                .collect::<BTreeMap<_, _>>()
+                        })
+                        .unwrap_or_default();
+                    storage.extend(post.storage.iter().map(|(slot, value)| (*slot, *value)));
+                    AccountUpdate {
+                        address: *address,
+                        nonce: post.nonce.map(U256::from),
+                        balance: post.balance,
+                        code_hash: post.code.as_ref().map(code_hash),
+                        storage: storage
+                            .into_iter()
+                            .map(|(slot, value)| (slot.into_uint(), value.into_uint()))
+                            .collect(),
+                        deleted: false,
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Fetches the proofs of every account and slot of `prestate` at `parent_block`,
+/// loads them into `trie` (see `fetch_proofs`) and returns the values the tracer
+/// reports differently. The pre state of a transaction is only the state of the
+/// parent block for the first transaction of a block, later ones see the changes
+/// of the transactions before them.
+pub async fn load_prestate<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    parent_block: BlockId,
+    prestate: &Prestate,
+    config: &FetchConfig,
+    trie: &mut StateTrie,
+) -> Result<Vec<PrestateMismatch>, Error> {
+    let targets = proof_targets(prestate);
+    for (_, result) in fetch_proofs(provider, parent_block, targets, config, trie).await {
+        result?;
+    }
+    check_prestate(trie, prestate)
+}
+
+// the tracer gives `0x` for an account without code
+fn code_hash(code: &Bytes) -> H256 {
+    if code.is_empty() {
+        EMPTY_CODE_HASH
+    } else {
+        H256::from(keccak256(code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ethers::{
+        types::{Address, H256, U256},
+        utils::keccak256,
+    };
+
+    use super::{
+        check_prestate, parse_prestate, parse_prestate_diff, proof_targets, PrestateMismatch,
+    };
+    use crate::state_root::state_trie::{AccountUpdate, StateTrie};
+
+    // geth --dev state of test_state_1
+    const STATE_ROOT: &str = "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909";
+    const CONTRACT: &str = "0x730e01e70b028b44a9387119d78e1392e4848cbc";
+    const SENDER: &str = "0x3736b9d9d35d8c4f41d98a412fe9211024453575";
+
+    fn loaded_trie() -> StateTrie {
+        let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+        trie.load_fixture_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/eth_getProof"))
+            .unwrap();
+        trie
+    }
+
+    fn slot(slot: u64) -> H256 {
+        H256::from_low_u64_be(slot)
+    }
+
+    #[test]
+    pub fn test_prestate_check_1() {
+        // slot[2] is 4, and the code of the contract is not 0x00
+        let prestate = parse_prestate(
+            r#"{
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": {
+                    "0x3736b9d9d35d8c4f41d98a412fe9211024453575": {
+                        "balance": "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82",
+                        "nonce": 3
+                    },
+                    "0x730e01e70b028b44a9387119d78e1392e4848cbc": {
+                        "balance": "0x0",
+                        "nonce": 1,
+                        "code": "0x00",
+                        "storage": {
+                            "0x0000000000000000000000000000000000000000000000000000000000000001": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                            "0x0000000000000000000000000000000000000000000000000000000000000002": "0x0000000000000000000000000000000000000000000000000000000000000005"
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let contract: Address = CONTRACT.parse().unwrap();
+        let sender: Address = SENDER.parse().unwrap();
+        assert_eq!(
+            proof_targets(&prestate),
+            vec![(sender, vec![]), (contract, vec![slot(1), slot(2)])]
+        );
+
+        let trie = loaded_trie();
+        assert_eq!(
+            check_prestate(&trie, &prestate).unwrap(),
+            vec![
+                PrestateMismatch::CodeHash(
+                    contract,
+                    H256::from(keccak256([0u8])),
+                    "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16"
+                        .parse()
+                        .unwrap()
+                ),
+                PrestateMismatch::Storage(contract, slot(2), 5.into(), 4.into()),
+            ]
+        );
+
+        // an account the proofs do not cover
+        let mut prestate = prestate;
+        prestate.insert(Address::from_low_u64_be(1), Default::default());
+        assert!(check_prestate(&trie, &prestate).is_err());
+    }
+
+    #[test]
+    pub fn test_prestate_diff_1() {
+        // the sender calls the contract, which sets slot[1] = 8 and clears slot[2],
+        // while 0x..0d is destructed
+        let diff = parse_prestate_diff(
+            r#"{
+                "pre": {
+                    "0x000000000000000000000000000000000000000d": {
+                        "balance": "0x0"
+                    },
+                    "0x3736b9d9d35d8c4f41d98a412fe9211024453575": {
+                        "balance": "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82",
+                        "nonce": 3
+                    },
+                    "0x730e01e70b028b44a9387119d78e1392e4848cbc": {
+                        "nonce": 1,
+                        "storage": {
+                            "0x0000000000000000000000000000000000000000000000000000000000000001": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                            "0x0000000000000000000000000000000000000000000000000000000000000002": "0x0000000000000000000000000000000000000000000000000000000000000004"
+                        }
+                    }
+                },
+                "post": {
+                    "0x3736b9d9d35d8c4f41d98a412fe9211024453575": {
+                        "balance": "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c00",
+                        "nonce": 4
+                    },
+                    "0x730e01e70b028b44a9387119d78e1392e4848cbc": {
+                        "storage": {
+                            "0x0000000000000000000000000000000000000000000000000000000000000001": "0x0000000000000000000000000000000000000000000000000000000000000008"
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let contract: Address = CONTRACT.parse().unwrap();
+        let sender: Address = SENDER.parse().unwrap();
+        let balance: U256 = "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c00"
+            .parse()
+            .unwrap();
+
+        let updates = diff.account_updates();
+        assert_eq!(
+            updates,
+            vec![
+                AccountUpdate {
+                    address: Address::from_low_u64_be(13),
+                    deleted: true,
+                    ..Default::default()
+                },
+                AccountUpdate {
+                    address: sender,
+                    nonce: Some(4.into()),
+                    balance: Some(balance),
+                    ..Default::default()
+                },
+                AccountUpdate {
+                    address: contract,
+                    storage: vec![(1.into(), 8.into()), (2.into(), 0.into())],
+                    ..Default::default()
+                },
+            ]
+        );
+
+        let mut trie = loaded_trie();
+        trie.apply_updates(&updates).unwrap();
+        let storage_root = trie.account_trie.storage_root_for(contract).unwrap();
+        let storage_trie = trie.get_storage_trie(storage_root);
+        assert_eq!(storage_trie.get_value(1.into()).unwrap(), U256::from(8));
+        assert_eq!(storage_trie.get_value(2.into()).unwrap(), U256::zero());
+        assert_eq!(
+            trie.account_trie.get_account_data(sender).unwrap().balance,
+            balance
+        );
+    }
+}
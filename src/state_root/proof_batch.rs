@@ -0,0 +1,192 @@
+use ethers::types::{Bytes, H256};
+
+use crate::error::Error;
+
+// Binary framing for shipping many proofs against the same root:
+//
+//   root (32 bytes) | entry count (u32)
+//   per entry: key len (u32) | key | value len (u32) | value
+//              | proof node count (u32) | per node: node len (u32) | node
+//
+// all integers are big endian.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProofEntry {
+    // raw path in the trie, i.e. the hashed key for secure tries
+    pub key: Bytes,
+    pub value: Bytes,
+    pub proof: Vec<Bytes>,
+}
+
+/// Errors with `Error::ProofBatchTooLarge` if a length or count does not fit the
+/// u32 it is framed with.
+pub fn encode_proof_batch(root: H256, entries: &[ProofEntry]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    out.extend_from_slice(root.as_bytes());
+    push_u32(&mut out, entries.len())?;
+    for entry in entries {
+        push_bytes(&mut out, &entry.key)?;
+        push_bytes(&mut out, &entry.value)?;
+        push_u32(&mut out, entry.proof.len())?;
+        for node in entry.proof.iter() {
+            push_bytes(&mut out, node)?;
+        }
+    }
+    Ok(out)
+}
+
+pub fn decode_proof_batch(bytes: &[u8]) -> Result<(H256, Vec<ProofEntry>), Error> {
+    let mut reader = Reader { bytes, offset: 0 };
+    let root = H256::from_slice(reader.take(32)?);
+
+    let num_entries = reader.read_u32()?;
+    // counts are not trusted for preallocation, a truncated buffer errors out below anyway
+    let mut entries = Vec::new();
+    for _ in 0..num_entries {
+        let key = reader.read_bytes()?;
+        let value = reader.read_bytes()?;
+        let num_nodes = reader.read_u32()?;
+        let mut proof = Vec::new();
+        for _ in 0..num_nodes {
+            proof.push(reader.read_bytes()?);
+        }
+        entries.push(ProofEntry { key, value, proof });
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(Error::InvalidProofBatch(
+            "has trailing bytes",
+            reader.offset,
+        ));
+    }
+    Ok((root, entries))
+}
+
+fn push_u32(out: &mut Vec<u8>, value: usize) -> Result<(), Error> {
+    let value = u32::try_from(value).map_err(|_| Error::ProofBatchTooLarge(value))?;
+    out.extend_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Error> {
+    push_u32(out, bytes.len())?;
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(Error::InvalidProofBatch("is truncated", self.offset))?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn read_bytes(&mut self) -> Result<Bytes, Error> {
+        let len = self.read_u32()? as usize;
+        Ok(Bytes::from(self.take(len)?.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_proof_batch, encode_proof_batch, push_u32, ProofEntry};
+    use crate::error::Error;
+    use ethers::types::H256;
+
+    fn sample_entries() -> Vec<ProofEntry> {
+        vec![
+            ProofEntry {
+                key: "0x405787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace"
+                    .parse()
+                    .unwrap(),
+                value: "0x04".parse().unwrap(),
+                proof: vec![
+                    "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc580808080808080a055037b5dac295c1605ec14cf282314a2870cbf448e24cf0cbc1b46fc09ad731e80808080".parse().unwrap(),
+                    "0xe2a0305787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace04".parse().unwrap(),
+                ],
+            },
+            ProofEntry {
+                key: "0xc2575a0e9e593c00f959f8c92f12db2869c3395a3b0502d05e2516446f71f85b"
+                    .parse()
+                    .unwrap(),
+                value: "0x09".parse().unwrap(),
+                proof: vec![
+                    "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc580808080808080a055037b5dac295c1605ec14cf282314a2870cbf448e24cf0cbc1b46fc09ad731e80808080".parse().unwrap(),
+                    "0xe2a032575a0e9e593c00f959f8c92f12db2869c3395a3b0502d05e2516446f71f85b09".parse().unwrap(),
+                ],
+            },
+        ]
+    }
+
+    #[test]
+    pub fn test_proof_batch_round_trip_1() {
+        let root: H256 = "0xe730900f060334776424339bad2d8fb6f53d8b2ddbf991f492d852fb119addc0"
+            .parse()
+            .unwrap();
+        let entries = sample_entries();
+
+        let encoded = encode_proof_batch(root, &entries).unwrap();
+        let (root_decoded, entries_decoded) = decode_proof_batch(&encoded).unwrap();
+
+        assert_eq!(root_decoded, root);
+        assert_eq!(entries_decoded, entries);
+    }
+
+    #[test]
+    pub fn test_proof_batch_round_trip_2_empty() {
+        let root = H256::zero();
+        let encoded = encode_proof_batch(root, &[]).unwrap();
+        assert_eq!(encoded.len(), 36);
+
+        let (root_decoded, entries_decoded) = decode_proof_batch(&encoded).unwrap();
+        assert_eq!(root_decoded, root);
+        assert!(entries_decoded.is_empty());
+    }
+
+    #[test]
+    pub fn test_proof_batch_malformed() {
+        let encoded = encode_proof_batch(H256::zero(), &sample_entries()).unwrap();
+
+        // every truncation point must be rejected without panicking
+        for len in 0..encoded.len() {
+            assert!(decode_proof_batch(&encoded[..len]).is_err());
+        }
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(decode_proof_batch(&trailing).is_err());
+
+        // huge entry count with no entries following
+        let mut huge_count = H256::zero().as_bytes().to_vec();
+        huge_count.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode_proof_batch(&huge_count).is_err());
+    }
+
+    #[test]
+    pub fn test_proof_batch_length_over_u32() {
+        // a length that does not fit is an error and not a wrapped around u32
+        let mut out = Vec::new();
+        push_u32(&mut out, u32::MAX as usize).unwrap();
+        assert!(matches!(
+            push_u32(&mut out, u32::MAX as usize + 1),
+            Err(Error::ProofBatchTooLarge(len)) if len == u32::MAX as usize + 1
+        ));
+        assert_eq!(out, u32::MAX.to_be_bytes());
+    }
+}
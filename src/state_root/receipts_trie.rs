@@ -0,0 +1,257 @@
+use super::{
+    utils::{Nibbles, Trie},
+    withdrawals_trie::indexed,
+};
+use crate::error::{DecodeContext, Error};
+use ethers::{
+    prelude::EthDisplay,
+    types::{Bytes, TransactionReceipt, H256},
+    utils::rlp::{Rlp, RlpStream},
+};
+
+/// Trie of the receipts of a block, whose root is the `receiptsRoot` of the
+/// header. Like the withdrawals trie its keys are not hashed, a receipt is keyed
+/// by the RLP of its position in the block and holds its consensus encoding (see
+/// `encode_receipt`).
+#[derive(Debug, Clone, EthDisplay, PartialEq)]
+pub struct ReceiptsTrie(Trie);
+
+/// Proof that the receipt at `tx_index` of a block is `receipt_rlp`, along with
+/// where the RLP of one of its logs sits in `receipt_rlp`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceiptProof {
+    pub tx_index: u64,
+    pub receipt_rlp: Bytes,
+    pub proof: Vec<Bytes>,
+    // the RLP of the log is `receipt_rlp[log_offset..log_offset + log_len]`
+    pub log_offset: usize,
+    pub log_len: usize,
+}
+
+impl Default for ReceiptsTrie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReceiptsTrie {
+    pub fn new() -> Self {
+        ReceiptsTrie(indexed(Trie::new()))
+    }
+
+    pub fn from_root(root: H256) -> Self {
+        ReceiptsTrie(indexed(Trie::from_root(root)))
+    }
+
+    /// Builds the trie of a whole block, `receipts` being in transaction order.
+    pub fn from_receipts(receipts: &[TransactionReceipt]) -> Result<Self, Error> {
+        let trie = Trie::from_entries(
+            receipts
+                .iter()
+                .enumerate()
+                .map(|(index, receipt)| {
+                    (Nibbles::from_index(index as u64), encode_receipt(receipt))
+                })
+                .collect(),
+        )?;
+        Ok(ReceiptsTrie(indexed(trie)))
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.0.root
+    }
+
+    // consensus encoding of the receipt, empty if the block has none at `index`
+    pub fn get_receipt(&self, index: u64) -> Result<Bytes, Error> {
+        self.0.get_value(Nibbles::from_index(index))
+    }
+
+    /// `receipt_rlp` is empty for a proof that the block has no receipt at `index`.
+    pub fn load_proof(
+        &mut self,
+        index: u64,
+        receipt_rlp: Bytes,
+        proof: Vec<Bytes>,
+    ) -> Result<(), Error> {
+        self.0
+            .load_proof(Nibbles::from_index(index), receipt_rlp, proof)
+    }
+
+    /// Proof of the receipt at `tx_index`, locating the log at `log_index` in it.
+    pub fn prove_log(&self, tx_index: u64, log_index: usize) -> Result<ReceiptProof, Error> {
+        let receipt_rlp = self.get_receipt(tx_index)?;
+        if receipt_rlp.is_empty() {
+            return Err(Error::ReceiptNotFound(tx_index));
+        }
+        let (log_offset, log_len) = log_range(&receipt_rlp, log_index)?;
+        Ok(ReceiptProof {
+            tx_index,
+            proof: self.0.prove(Nibbles::from_index(tx_index))?,
+            receipt_rlp,
+            log_offset,
+            log_len,
+        })
+    }
+}
+
+/// Proof that the log at `log_index` of the transaction at `tx_index` was emitted
+/// in the block whose receipts are `receipts`, to check against the `receiptsRoot`
+/// of its header with `verify_receipt_proof`.
+pub fn prove_log(
+    receipts: &[TransactionReceipt],
+    tx_index: u64,
+    log_index: usize,
+) -> Result<ReceiptProof, Error> {
+    ReceiptsTrie::from_receipts(receipts)?.prove_log(tx_index, log_index)
+}
+
+/// Checks that the receipt at `tx_index` of the block with `receipts_root` is
+/// `receipt_rlp`.
+pub fn verify_receipt_proof(
+    receipts_root: H256,
+    tx_index: u64,
+    receipt_rlp: Bytes,
+    proof: Vec<Bytes>,
+) -> Result<(), Error> {
+    ReceiptsTrie::from_root(receipts_root).load_proof(tx_index, receipt_rlp, proof)
+}
+
+/// RLP of `[status, cumulative_gas_used, logs_bloom, logs]`, the status being the
+/// post state root before Byzantium. Receipts of typed transactions are prefixed
+/// with the transaction type (EIP-2718).
+pub fn encode_receipt(receipt: &TransactionReceipt) -> Bytes {
+    let mut stream = RlpStream::new_list(4);
+    match receipt.status {
+        Some(status) => stream.append(&status),
+        None => stream.append(&receipt.root.unwrap_or_default()),
+    };
+    stream.append(&receipt.cumulative_gas_used);
+    stream.append(&receipt.logs_bloom);
+    stream.begin_list(receipt.logs.len());
+    for log in &receipt.logs {
+        stream.begin_list(3);
+        stream.append(&log.address);
+        stream.append_list(&log.topics);
+        stream.append(&log.data);
+    }
+
+    let mut encoded = Vec::new();
+    match receipt.transaction_type.map(|tx_type| tx_type.as_u64()) {
+        None | Some(0) => {}
+        Some(tx_type) => encoded.push(tx_type as u8),
+    }
+    encoded.extend_from_slice(&stream.out());
+    Bytes::from(encoded)
+}
+
+// (offset, length) of the RLP of the log at `log_index` in `receipt_rlp`
+fn log_range(receipt_rlp: &[u8], log_index: usize) -> Result<(usize, usize), Error> {
+    // the type of a typed receipt is at most 0x7f, an RLP list starts at 0xc0
+    let mut offset = match receipt_rlp.first() {
+        Some(first) if *first <= 0x7f => 1,
+        _ => 0,
+    };
+    let receipt = Rlp::new(&receipt_rlp[offset..]);
+    offset += receipt.payload_info().decoding("receipt")?.header_len;
+    for i in 0..3 {
+        offset += receipt.at(i).decoding("receipt")?.as_raw().len();
+    }
+
+    let logs = receipt.at(3).decoding("receipt logs")?;
+    let log_count = logs.item_count().decoding("receipt logs")?;
+    if log_index >= log_count {
+        return Err(Error::LogNotFound(log_index, log_count));
+    }
+    offset += logs.payload_info().decoding("receipt logs")?.header_len;
+    for i in 0..log_index {
+        offset += logs.at(i).decoding("receipt log")?.as_raw().len();
+    }
+    let log = logs.at(log_index).decoding("receipt log")?;
+    Ok((offset, log.as_raw().len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prove_log, verify_receipt_proof, Bytes, ReceiptsTrie, H256};
+    use ethers::types::{Address, Log, TransactionReceipt, U64};
+
+    // receipts trie of three_receipts
+    const RECEIPTS_ROOT: &str =
+        "0x783c3b9d515562dba5d8a55c28d1b0eefb95c76d5d2f99ffcefa93600f678616";
+
+    fn log(address: u8, topics: &[u8], data: &str) -> Log {
+        Log {
+            address: Address::repeat_byte(address),
+            topics: topics
+                .iter()
+                .map(|topic| H256::repeat_byte(*topic))
+                .collect(),
+            data: data.parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    // a legacy receipt with one log, an EIP-1559 one with two and a failed EIP-2930
+    // one with none, with empty blooms as the trie does not look into them
+    fn three_receipts() -> Vec<TransactionReceipt> {
+        vec![
+            TransactionReceipt {
+                status: Some(U64::from(1)),
+                cumulative_gas_used: 21000.into(),
+                logs: vec![log(
+                    0x11,
+                    &[0xaa],
+                    "0x000000000000000000000000000000000000000000000000000000000000002a",
+                )],
+                ..Default::default()
+            },
+            TransactionReceipt {
+                status: Some(U64::from(1)),
+                cumulative_gas_used: 74000.into(),
+                logs: vec![log(0x22, &[0xbb, 0xcc], "0x"), log(0x33, &[], "0xdeadbeef")],
+                transaction_type: Some(U64::from(2)),
+                ..Default::default()
+            },
+            TransactionReceipt {
+                status: Some(U64::from(0)),
+                cumulative_gas_used: 95000.into(),
+                transaction_type: Some(U64::from(1)),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    pub fn test_receipts_trie_prove_log_1() {
+        let root: H256 = RECEIPTS_ROOT.parse().unwrap();
+        let receipts = three_receipts();
+        let trie = ReceiptsTrie::from_receipts(&receipts).unwrap();
+        assert_eq!(trie.root(), Some(root));
+
+        // second log of the typed receipt, below the branch of indexes 1 and 2
+        let proof = prove_log(&receipts, 1, 1).unwrap();
+        assert_eq!(proof.proof.len(), 3);
+        assert_eq!(proof.receipt_rlp[0], 0x02);
+        let log: Bytes = "0xdb943333333333333333333333333333333333333333c084deadbeef"
+            .parse()
+            .unwrap();
+        assert_eq!((proof.log_offset, proof.log_len), (362, log.len()));
+        assert_eq!(
+            proof.receipt_rlp[proof.log_offset..proof.log_offset + proof.log_len],
+            log[..]
+        );
+        verify_receipt_proof(root, 1, proof.receipt_rlp.clone(), proof.proof.clone()).unwrap();
+        // the same receipt claimed at another index
+        assert!(verify_receipt_proof(root, 2, proof.receipt_rlp, proof.proof).is_err());
+
+        // the only log of the legacy receipt, which has no type byte
+        let proof = trie.prove_log(0, 0).unwrap();
+        assert_eq!(proof.receipt_rlp[0], 0xf9);
+        assert_eq!((proof.log_offset, proof.log_len), (268, 90));
+        verify_receipt_proof(root, 0, proof.receipt_rlp, proof.proof).unwrap();
+
+        // no log in the failed receipt, no receipt past the last transaction
+        assert!(trie.prove_log(2, 0).is_err());
+        assert!(trie.prove_log(3, 0).is_err());
+    }
+}
@@ -1,43 +1,373 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use serde::Serialize;
 
 use ethers::{
     prelude::EthDisplay,
-    types::{Address, EIP1186ProofResponse, H256, U256},
+    types::{Address, BigEndianHash, Block, Bytes, EIP1186ProofResponse, H256, U256},
+    utils::{keccak256, rlp::Rlp},
 };
 
-use crate::error::Error;
+use crate::error::{DecodeContext, Error};
 
 use super::{
-    account_trie::{AccountData, AccountTrie},
-    storage_trie::StorageTrie,
+    account_proof::{AccountProof, StorageSlotProof},
+    account_trie::{AccountData, AccountKey, AccountTrie},
+    checkpoints::Checkpoints,
+    storage_trie::{decode_value, StorageKey, StorageTrie},
+    utils::{
+        account_key, bytes_to_h256, empty_root, storage_key, KeyStatus, LoadLimits, LoadUsage,
+        PathStatus, WitnessSize,
+    },
 };
 
+/// State trie made of the account trie and the storage tries of loaded accounts.
+///
+/// `StateTrie` is `Send + Sync`, so once the pre state is loaded it can be shared
+/// behind an `Arc` by concurrent readers. Reads (`root`, `get_storage_trie` and the
+/// getters on `account_trie`) take `&self`, while loading proofs and every state
+/// change (`load_proof`, `set_storage_value`, `delete_account`, setters on
+/// `account_trie`) take `&mut self` and need exclusive access.
+///
+/// Cloning does not copy the loaded nodes, clones share them and a change made to
+/// a trie of one clone copies only the nodes on the path it changes. The map of
+/// storage tries is copied on the first change to one of them, which clones each
+/// trie without copying its nodes. Several candidate transactions can so each run
+/// on a clone of the same pre state.
 #[derive(Clone, Debug, EthDisplay, PartialEq)]
 pub struct StateTrie {
     pub account_trie: AccountTrie,
-    storage_tries: HashMap<H256, StorageTrie>,
+    storage_tries: Arc<HashMap<H256, StorageTrie>>,
+    // the tries are keyed by hashes, so addresses and slots are tracked as they come in
+    preimages: Arc<BTreeMap<Address, BTreeSet<U256>>>,
+    // root the proofs are loaded against, the account trie root moves with changes
+    original_root: Option<H256>,
+    // changes since the oldest open snapshot, empty while none is open
+    journal: Vec<StateChange>,
+    snapshots: Vec<Snapshot>,
+    // writes recorded for the MPT circuit, None unless enabled with `enable_journal`
+    updates: Option<Vec<MptUpdate>>,
+    // limits on the proofs loaded into all the tries together
+    limits: LoadLimits,
+    usage: LoadUsage,
+}
+
+/// A single write to the state as the MPT circuit proves it, a transition of the
+/// state root from `old_root` to `new_root`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MptUpdate {
+    pub old_root: H256,
+    pub new_root: H256,
+    pub key: MptKey,
+    // code hashes are taken as words, both values are zero for a destructed account
+    pub old_value: U256,
+    pub new_value: U256,
+    pub kind: MptUpdateKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MptKey {
+    Account(Address),
+    Storage { address: Address, slot: U256 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MptUpdateKind {
+    Nonce,
+    Balance,
+    CodeHash,
+    Storage,
+    AccountDestructed,
+}
+
+/// Post state of an account for `StateTrie::apply_updates`, fields left `None`
+/// keep their value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountUpdate {
+    pub address: Address,
+    pub nonce: Option<U256>,
+    pub balance: Option<U256>,
+    pub code_hash: Option<H256>,
+    // (slot, value) written in order, a zero value clears the slot
+    pub storage: Vec<(U256, U256)>,
+    // the account is destructed, the other fields are then ignored
+    pub deleted: bool,
+}
+
+/// Identifies a snapshot taken with `StateTrie::snapshot`. Ids of reverted
+/// snapshots are handed out again, so an id is only valid until it is reverted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotId(usize);
+
+#[derive(Clone, Debug, PartialEq)]
+struct Snapshot {
+    // checkpoint of the account trie journal
+    account_trie: usize,
+    // position in the state journal
+    journal: usize,
+    // number of writes recorded for the MPT circuit
+    updates: usize,
+    root: Option<H256>,
+}
+
+/// A change to the state outside the account trie, holding what is needed to undo
+/// it. Changes to the nodes of the tries are recorded by the tries themselves.
+#[derive(Clone, Debug, PartialEq)]
+enum StateChange {
+    // storage trie moved to its new root after the changes its journal records from
+    // `checkpoint`, along with the storage trie it replaced at that root
+    StorageTrieUpdated {
+        root_old: H256,
+        root_new: H256,
+        checkpoint: usize,
+        replaced: Option<StorageTrie>,
+    },
+    // storage trie inserted or removed at `root`, along with the one held before
+    StorageTrieReplaced {
+        root: H256,
+        previous: Option<StorageTrie>,
+    },
+    Preimages {
+        address: Address,
+        previous: Option<BTreeSet<U256>>,
+    },
+    OriginalRoot(Option<H256>),
+}
+
+/// Witness estimate of a loaded state, see `Trie::witness_size`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StateWitnessSize {
+    pub account_trie: WitnessSize,
+    // all storage tries summed up
+    pub storage_tries: WitnessSize,
+    pub accounts: usize,
+    pub storage_slots: usize,
+    pub code_bytes: usize,
+}
+
+/// Everything loaded in a `StateTrie`, see `StateTrie::dump`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StateDump {
+    pub root: Option<H256>,
+    pub accounts: Vec<AccountDump>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AccountDump {
+    pub key: AccountKey,
+    pub nonce: U256,
+    pub balance: U256,
+    pub storage_root: H256,
+    pub code_hash: H256,
+    // empty when no storage proof of the account is loaded
+    pub storage: Vec<(StorageKey, U256)>,
 }
 
 impl StateTrie {
     pub fn new() -> Self {
         StateTrie {
             account_trie: AccountTrie::new(),
-            storage_tries: HashMap::new(),
+            storage_tries: Arc::new(HashMap::new()),
+            preimages: Arc::new(BTreeMap::new()),
+            original_root: None,
+            journal: Vec::new(),
+            snapshots: Vec::new(),
+            updates: None,
+            limits: LoadLimits::default(),
+            usage: LoadUsage::default(),
         }
     }
 
+    pub fn empty() -> Self {
+        StateTrie::from_root(empty_root())
+    }
+
     pub fn from_root(root: H256) -> Self {
         StateTrie {
             account_trie: AccountTrie::from_root(root),
-            storage_tries: HashMap::new(),
+            storage_tries: Arc::new(HashMap::new()),
+            preimages: Arc::new(BTreeMap::new()),
+            original_root: Some(root),
+            journal: Vec::new(),
+            snapshots: Vec::new(),
+            updates: None,
+            limits: LoadLimits::default(),
+            usage: LoadUsage::default(),
+        }
+    }
+
+    /// Like `from_root`, for a `root` that must be the state root of one of the
+    /// `checkpoints`, so that proofs are only checked against a trusted state.
+    pub fn new_checked(root: H256, checkpoints: &Checkpoints) -> Result<Self, Error> {
+        if !checkpoints.contains_root(root) {
+            return Err(Error::UntrustedRoot(root));
+        }
+        Ok(StateTrie::from_root(root))
+    }
+
+    /// Pre state of the block after `header`, proofs of any other block are rejected
+    /// when loaded.
+    pub fn for_block(header: &Block<H256>) -> Self {
+        StateTrie::from_root(header.state_root)
+    }
+
+    /// Pre state of the block after the one whose RLP encoded header is
+    /// `header_rlp`, along with the hash of that header to check against the parent
+    /// hash of the next block. See `BlockHeader::decode` for the other fields.
+    pub fn from_header(header_rlp: Bytes) -> Result<(Self, H256), Error> {
+        let header = BlockHeader::decode(header_rlp)?;
+        Ok((StateTrie::from_root(header.state_root), header.hash))
+    }
+
+    /// Drops everything loaded and starts over at `root`, for going through one
+    /// block after the other without a new trie each time. The snapshots and the
+    /// recorded writes go too, while the limits and whether writes are recorded
+    /// (see `enable_journal`) are kept, as is the memory of the tries unless a clone
+    /// still shares it.
+    pub fn reset(&mut self, root: H256) {
+        self.account_trie.reset(root);
+        match Arc::get_mut(&mut self.storage_tries) {
+            Some(storage_tries) => storage_tries.clear(),
+            None => self.storage_tries = Arc::new(HashMap::new()),
         }
+        self.preimages = Arc::new(BTreeMap::new());
+        self.original_root = Some(root);
+        self.journal.clear();
+        self.snapshots.clear();
+        if let Some(updates) = self.updates.as_mut() {
+            updates.clear();
+        }
+        self.usage = LoadUsage::default();
+    }
+
+    /// `reset` to the pre state of the block after `header`, as `for_block`.
+    pub fn reset_for_block(&mut self, header: &Block<H256>) {
+        self.reset(header.state_root);
     }
 
     pub fn root(&self) -> Option<H256> {
         self.account_trie.root()
     }
 
-    pub fn get_storage_trie(&mut self, storage_root: H256) -> StorageTrie {
+    /// Limits on the account and storage proofs loaded with `load_proof` and
+    /// `load_account_proof`, counted over all the tries together. An account proof
+    /// that would cross one is rejected with `Error::LimitExceeded` as a whole,
+    /// before any of it is loaded.
+    pub fn set_load_limits(&mut self, limits: LoadLimits) {
+        self.limits = limits;
+        self.account_trie.set_load_limits(limits);
+        for storage_trie in Arc::make_mut(&mut self.storage_tries).values_mut() {
+            storage_trie.set_load_limits(limits);
+        }
+    }
+
+    // nodes held by the account trie and the storage tries
+    fn node_count(&self) -> usize {
+        self.account_trie.node_count()
+            + self
+                .storage_tries
+                .values()
+                .map(StorageTrie::node_count)
+                .sum::<usize>()
+    }
+
+    /// Addresses of the loaded accounts along with the storage slots loaded or set on
+    /// each, in order.
+    pub fn loaded_keys(&self) -> impl Iterator<Item = (&Address, &BTreeSet<U256>)> {
+        self.preimages.iter()
+    }
+
+    /// Accounts whose full path is loaded, sorted by hashed address. An account is
+    /// keyed by its address unless it was loaded on `account_trie` directly.
+    pub fn accounts(&self) -> impl Iterator<Item = (AccountKey, AccountData)> {
+        let addresses = self
+            .preimages
+            .keys()
+            .map(|address| (account_key(*address), *address))
+            .collect::<HashMap<_, _>>();
+        self.account_trie
+            .entries()
+            .map(move |(hash, account_data)| match addresses.get(&hash) {
+                Some(address) => (AccountKey::Address(*address), account_data),
+                None => (AccountKey::Hashed(hash), account_data),
+            })
+    }
+
+    /// Snapshot of the loaded accounts and the storage known of each, in the order
+    /// of `accounts` and `StorageTrie::entries`. Slots are keyed by their preimage
+    /// when it is known.
+    pub fn dump(&self) -> StateDump {
+        let accounts = self
+            .accounts()
+            .map(|(key, account_data)| {
+                let slots = match key {
+                    AccountKey::Address(address) => self.preimages.get(&address),
+                    AccountKey::Hashed(_) => None,
+                }
+                .into_iter()
+                .flatten()
+                .map(|slot| (storage_key(*slot), *slot))
+                .collect::<HashMap<_, _>>();
+                let storage = self
+                    .get_storage_trie(account_data.storage_root)
+                    .entries()
+                    .map(
+                        |(storage_key, value)| match slots.get(&storage_key.hashed()) {
+                            Some(slot) => (StorageKey::Slot(*slot), value),
+                            None => (storage_key, value),
+                        },
+                    )
+                    .collect();
+                AccountDump {
+                    key,
+                    nonce: account_data.nonce,
+                    balance: account_data.balance,
+                    storage_root: account_data.storage_root,
+                    code_hash: account_data.code_hash,
+                    storage,
+                }
+            })
+            .collect();
+        StateDump {
+            root: self.root(),
+            accounts,
+        }
+    }
+
+    /// `code` holds the bytecode by code hash, the code of every loaded account found
+    /// in it is counted once.
+    pub fn witness_size(&self, code: &HashMap<H256, Bytes>) -> StateWitnessSize {
+        let mut storage_tries = WitnessSize::default();
+        for storage_trie in self.storage_tries.values() {
+            storage_tries.add(&storage_trie.witness_size());
+        }
+
+        let code_hashes = self
+            .preimages
+            .keys()
+            .filter_map(|address| self.account_trie.get_account_data(*address).ok())
+            .map(|account_data| account_data.code_hash)
+            .collect::<BTreeSet<_>>();
+
+        StateWitnessSize {
+            account_trie: self.account_trie.witness_size(),
+            storage_tries,
+            accounts: self.preimages.len(),
+            storage_slots: self.preimages.values().map(|slots| slots.len()).sum(),
+            code_bytes: code_hashes
+                .iter()
+                .filter_map(|code_hash| code.get(code_hash))
+                .map(|bytecode| bytecode.len())
+                .sum(),
+        }
+    }
+
+    pub fn get_storage_trie(&self, storage_root: H256) -> StorageTrie {
         if !self.storage_tries.contains_key(&storage_root) {
             StorageTrie::from_root(storage_root)
         } else {
@@ -45,54 +375,649 @@ impl StateTrie {
         }
     }
 
+    /// Of the `(address, slots)` targets, the proofs still to be loaded before the
+    /// accounts and slots can be read or given a value, in the form `fetch_proofs`
+    /// takes. An account whose path is not loaded comes back with all its slots, a
+    /// loaded one with the slots whose path is not. An account proven absent and the
+    /// slots of an account without storage need no proof. Clearing a slot or
+    /// deleting an account can still need a sibling node besides these, see
+    /// `Trie::is_path_loaded`.
+    pub fn missing_proofs(
+        &self,
+        targets: &[(Address, Vec<H256>)],
+    ) -> Result<Vec<(Address, Vec<H256>)>, Error> {
+        let mut missing = Vec::new();
+        for (address, slots) in targets {
+            if self.account_trie.is_path_loaded(*address)? != PathStatus::Complete {
+                missing.push((*address, slots.clone()));
+                continue;
+            }
+            let storage_root = match self.account_trie.contains(*address)? {
+                KeyStatus::Present(raw) => AccountData::from_rlp(raw)?.storage_root,
+                _ => continue,
+            };
+
+            let storage_trie = self.get_storage_trie(storage_root);
+            let mut slots_missing = Vec::new();
+            for slot in slots {
+                if storage_trie.is_path_loaded(slot.into_uint())? != PathStatus::Complete {
+                    slots_missing.push(*slot);
+                }
+            }
+            if !slots_missing.is_empty() {
+                missing.push((*address, slots_missing));
+            }
+        }
+        Ok(missing)
+    }
+
     pub fn set_storage_value(
         &mut self,
         address: Address,
         slot: U256,
         value: U256,
     ) -> Result<(), Error> {
+        let old_root = self.root().ok_or(Error::RootNotSet)?;
         let mut account_data = self.account_trie.get_account_data(address)?;
-        let mut storage_trie = self
-            .storage_tries
-            .remove(&account_data.storage_root)
+        let root_old = account_data.storage_root;
+        let mut storage_trie = Arc::make_mut(&mut self.storage_tries)
+            .remove(&root_old)
             .expect("storage trie not present, this should not happen");
-        storage_trie.set_value(slot, value)?;
-        account_data.storage_root = storage_trie.root().unwrap();
-        self.storage_tries
-            .insert(storage_trie.root().unwrap(), storage_trie);
+        // other accounts with the same storage, such as the empty one, keep theirs
+        if self.storage_root_users(root_old) > 1 {
+            Arc::make_mut(&mut self.storage_tries).insert(root_old, storage_trie.clone());
+        }
+        // the storage trie only records its changes while a snapshot is open
+        let checkpoint = (!self.snapshots.is_empty()).then(|| storage_trie.checkpoint());
+        let journaling = self.updates.is_some();
+        let written = (|| {
+            // the old value is only needed for the recorded update
+            let old_value = match journaling {
+                true => match storage_trie.contains(slot)? {
+                    KeyStatus::Present(raw) => decode_value(raw)?,
+                    KeyStatus::Absent => U256::zero(),
+                    KeyStatus::Unknown { missing_hash, .. } => {
+                        return Err(Error::MissingTrieNode(missing_hash))
+                    }
+                },
+                false => U256::zero(),
+            };
+            storage_trie.set_value(slot, value)?;
+            Ok(old_value)
+        })();
+        let old_value = match written {
+            Ok(old_value) => old_value,
+            Err(err) => {
+                // a failed write leaves the storage trie in place, as it was
+                Arc::make_mut(&mut self.storage_tries).insert(root_old, storage_trie);
+                return Err(err);
+            }
+        };
+        let root_new = storage_trie.root().unwrap();
+        account_data.storage_root = root_new;
+        let replaced = Arc::make_mut(&mut self.storage_tries).insert(root_new, storage_trie);
+        if let Some(checkpoint) = checkpoint {
+            self.record(StateChange::StorageTrieUpdated {
+                root_old,
+                root_new,
+                checkpoint,
+                replaced,
+            });
+        }
         self.account_trie.set_account_data(address, account_data)?;
+        self.record_preimages(address);
+        Arc::make_mut(&mut self.preimages)
+            .entry(address)
+            .or_default()
+            .insert(slot);
+        self.record_update(
+            MptKey::Storage { address, slot },
+            MptUpdateKind::Storage,
+            old_root,
+            old_value,
+            value,
+        )
+    }
+
+    /// Writes the nonce, balance and code hash of an account. Each field that
+    /// changes is written on its own, in that order, so that it is recorded as its
+    /// own update when journaling. The storage root only changes through
+    /// `set_storage_value`.
+    ///
+    /// An account the loaded proofs show absent is created with the first write,
+    /// which fills the empty branch slot or splits the leaf or extension the path
+    /// diverges from. If the path runs into a node that is not loaded, this errors
+    /// with `Error::MissingTrieNode` and nothing is written.
+    ///
+    /// Writes made directly on `account_trie` are not recorded.
+    pub fn set_account(
+        &mut self,
+        address: Address,
+        account_data: AccountData,
+    ) -> Result<(), Error> {
+        let (current, created) = match self.account_trie.contains(address)? {
+            KeyStatus::Present(raw) => (AccountData::from_rlp(raw)?, false),
+            KeyStatus::Absent => (AccountData::empty(), true),
+            KeyStatus::Unknown { missing_hash, .. } => {
+                return Err(Error::MissingTrieNode(missing_hash))
+            }
+        };
+        if account_data.storage_root != current.storage_root {
+            return Err(Error::StorageRootChanged(
+                current.storage_root,
+                account_data.storage_root,
+            ));
+        }
+        let key = MptKey::Account(address);
+        let mut data = current.clone();
+
+        if account_data.nonce != current.nonce {
+            let old_root = self.root().ok_or(Error::RootNotSet)?;
+            data.nonce = account_data.nonce;
+            self.account_trie.set_account_data(address, data.clone())?;
+            self.record_update(
+                key,
+                MptUpdateKind::Nonce,
+                old_root,
+                current.nonce,
+                account_data.nonce,
+            )?;
+        }
+        if account_data.balance != current.balance {
+            let old_root = self.root().ok_or(Error::RootNotSet)?;
+            data.balance = account_data.balance;
+            self.account_trie.set_account_data(address, data.clone())?;
+            self.record_update(
+                key,
+                MptUpdateKind::Balance,
+                old_root,
+                current.balance,
+                account_data.balance,
+            )?;
+        }
+        if account_data.code_hash != current.code_hash {
+            let old_root = self.root().ok_or(Error::RootNotSet)?;
+            data.code_hash = account_data.code_hash;
+            self.account_trie.set_account_data(address, data)?;
+            self.record_update(
+                key,
+                MptUpdateKind::CodeHash,
+                old_root,
+                current.code_hash.into_uint(),
+                account_data.code_hash.into_uint(),
+            )?;
+        }
+
+        // an account left empty is not created
+        if created && self.account_trie.contains(address)? != KeyStatus::Absent {
+            self.record_preimages(address);
+            Arc::make_mut(&mut self.preimages)
+                .entry(address)
+                .or_default();
+            let root = empty_root();
+            if !self.storage_tries.contains_key(&root) {
+                let previous = Arc::make_mut(&mut self.storage_tries)
+                    .insert(root, StorageTrie::from_root(root));
+                self.record(StateChange::StorageTrieReplaced { root, previous });
+            }
+        }
         Ok(())
     }
 
-    pub fn load_proof(&mut self, proof: EIP1186ProofResponse) -> Result<(), Error> {
-        self.account_trie.load_proof(
-            proof.address,
-            AccountData {
-                balance: proof.balance,
-                nonce: U256::from(proof.nonce.as_u64()),
-                code_hash: proof.code_hash,
-                storage_root: proof.storage_hash,
-            },
-            proof.account_proof,
+    /// Removes the account along with its storage, as done by `SELFDESTRUCT`,
+    /// and returns the new state root. Crediting the beneficiary is left to the
+    /// caller through `AccountTrie::add_balance`. Deleting an account that does
+    /// not exist changes nothing and records no update.
+    pub fn delete_account(&mut self, address: Address) -> Result<H256, Error> {
+        let old_root = self.root().ok_or(Error::RootNotSet)?;
+        let account_data = match self.account_trie.delete_account(address)? {
+            Some(account_data) => account_data,
+            None => return Ok(old_root),
+        };
+        // the storage trie stays while another account has the same storage
+        let root = account_data.storage_root;
+        if self.storage_root_users(root) == 0 {
+            let previous = Arc::make_mut(&mut self.storage_tries).remove(&root);
+            self.record(StateChange::StorageTrieReplaced { root, previous });
+        }
+        self.record_preimages(address);
+        Arc::make_mut(&mut self.preimages).remove(&address);
+        self.record_update(
+            MptKey::Account(address),
+            MptUpdateKind::AccountDestructed,
+            old_root,
+            U256::zero(),
+            U256::zero(),
         )?;
+        self.root().ok_or(Error::RootNotSet)
+    }
+
+    // loaded accounts whose storage is the storage trie under `root`
+    fn storage_root_users(&self, root: H256) -> usize {
+        self.account_trie
+            .entries()
+            .filter(|(_, account_data)| account_data.storage_root == root)
+            .count()
+    }
+
+    /// Writes every update through `set_account`, `set_storage_value` and
+    /// `delete_account`, in order, and returns the new state root. An update of an
+    /// absent account creates it.
+    pub fn apply_updates(&mut self, updates: &[AccountUpdate]) -> Result<H256, Error> {
+        for update in updates {
+            if update.deleted {
+                self.delete_account(update.address)?;
+                continue;
+            }
+            let mut account_data = match self.account_trie.contains(update.address)? {
+                KeyStatus::Present(raw) => AccountData::from_rlp(raw)?,
+                KeyStatus::Absent => AccountData::empty(),
+                KeyStatus::Unknown { missing_hash, .. } => {
+                    return Err(Error::MissingTrieNode(missing_hash))
+                }
+            };
+            if let Some(nonce) = update.nonce {
+                account_data.nonce = nonce;
+            }
+            if let Some(balance) = update.balance {
+                account_data.balance = balance;
+            }
+            if let Some(code_hash) = update.code_hash {
+                account_data.code_hash = code_hash;
+            }
+            self.set_account(update.address, account_data)?;
+            for (slot, value) in &update.storage {
+                self.set_storage_value(update.address, *slot, *value)?;
+            }
+        }
+        self.root().ok_or(Error::RootNotSet)
+    }
+
+    /// Builds the `eth_getProof` response for `address` and `slots` at the current
+    /// root, from the loaded nodes along with every change applied since. An
+    /// account the loaded nodes prove absent is returned with the fields of an
+    /// empty account.
+    pub fn export_eip1186(
+        &self,
+        address: Address,
+        slots: &[U256],
+    ) -> Result<EIP1186ProofResponse, Error> {
+        let account = match self.account_trie.contains(address)? {
+            KeyStatus::Present(raw) => AccountData::from_rlp(raw)?,
+            KeyStatus::Absent => AccountData::empty(),
+            KeyStatus::Unknown { missing_hash, .. } => {
+                return Err(Error::MissingTrieNode(missing_hash))
+            }
+        };
+        let storage_trie = self.get_storage_trie(account.storage_root);
+        let storage_proofs = slots
+            .iter()
+            .map(|slot| {
+                Ok(StorageSlotProof {
+                    slot: *slot,
+                    value: storage_trie.get_value(*slot)?,
+                    proof: storage_trie.prove(*slot)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let proof = AccountProof {
+            address,
+            state_root: self.root().ok_or(Error::RootNotSet)?,
+            proof: self.account_trie.prove(address)?,
+            account,
+            storage_proofs,
+        };
+        proof.to_eip1186()
+    }
+
+    pub fn load_proof(&mut self, proof: EIP1186ProofResponse) -> Result<(), Error> {
+        // a trie without a root takes the one of the first proof loaded
+        let proof_root = proof
+            .account_proof
+            .first()
+            .map(|root_node| H256::from(keccak256(root_node)));
+        let state_root = match (self.original_root, proof_root) {
+            (Some(root), _) => root,
+            (None, Some(proof_root)) => proof_root,
+            (None, None) => return Err(Error::RootNotSet),
+        };
+        // a proof taken at another block starts from another root
+        if let Some(proof_root) = proof_root {
+            if proof_root != state_root {
+                return Err(Error::PreStateRootMismatch(state_root, proof_root));
+            }
+        }
+        self.load_account_proof(AccountProof::from_eip1186(state_root, proof))
+    }
+
+    /// Loads an `eth_getProof` response saved as JSON, either the result object or
+    /// the whole JSON-RPC response. Errors are wrapped in `FixtureError` naming the
+    /// file, an account that is already loaded is a `DuplicateAccount`.
+    pub fn load_fixture_file(&mut self, path: &Path) -> Result<(), Error> {
+        self.load_fixture(path)
+            .map_err(|err| Error::FixtureError(path.to_path_buf(), Box::new(err)))
+    }
+
+    /// Loads every `.json` file in the directory with `load_fixture_file`, in file
+    /// name order.
+    pub fn load_fixture_dir(&mut self, path: &Path) -> Result<(), Error> {
+        let mut files = fs::read_dir(path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|err| Error::FixtureError(path.to_path_buf(), Box::new(err.into())))?;
+        files.retain(|file| file.extension() == Some("json".as_ref()));
+        files.sort();
+
+        for file in files {
+            self.load_fixture_file(&file)?;
+        }
+        Ok(())
+    }
+
+    fn load_fixture(&mut self, path: &Path) -> Result<(), Error> {
+        let mut json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if let Some(result) = json.get_mut("result").map(serde_json::Value::take) {
+            json = result;
+        }
+        let proof: EIP1186ProofResponse = serde_json::from_value(json)?;
+        if self.preimages.contains_key(&proof.address) {
+            return Err(Error::DuplicateAccount(proof.address));
+        }
+        self.load_proof(proof)
+    }
+
+    /// Loads the account and storage proofs, only once all of them verify. An
+    /// exclusion proof loads the account as absent (see `AccountProof::is_absent`),
+    /// so that `set_account` can create it.
+    pub fn load_account_proof(&mut self, proof: AccountProof) -> Result<(), Error> {
+        // every entry is counted as a new node, as they are not hashed yet
+        let entries = proof.proof.iter().chain(
+            proof
+                .storage_proofs
+                .iter()
+                .flat_map(|storage_proof| storage_proof.proof.iter()),
+        );
+        let usage =
+            self.limits
+                .charge(self.usage, 1 + proof.storage_proofs.len(), entries.clone())?;
+        self.limits
+            .check_nodes(self.node_count() + entries.count())?;
 
-        let mut storage_trie = self.get_storage_trie(proof.storage_hash);
-        for proof in proof.storage_proof {
+        if let Some(root) = self.original_root {
+            if root != proof.state_root {
+                return Err(Error::PreStateRootMismatch(root, proof.state_root));
+            }
+        }
+        proof.verify()?;
+
+        if proof.is_absent()? {
+            self.account_trie
+                .load_absence_proof(proof.address, proof.proof)?;
+        } else {
+            self.account_trie
+                .load_proof(proof.address, proof.account.clone(), proof.proof)?;
+        }
+
+        let storage_root = proof.account.storage_root;
+        let mut storage_trie = self.get_storage_trie(storage_root);
+        storage_trie.set_load_limits(self.limits);
+        self.record_preimages(proof.address);
+        let slots = Arc::make_mut(&mut self.preimages)
+            .entry(proof.address)
+            .or_default();
+        for storage_proof in proof.storage_proofs {
             storage_trie.load_proof(
-                U256::from_big_endian(proof.key.as_bytes()),
-                proof.value, // error is here, value does not need to be 32 byte
-                proof.proof,
+                storage_proof.slot,
+                storage_proof.value,
+                storage_proof.proof,
             )?;
+            slots.insert(storage_proof.slot);
+        }
+        let previous = Arc::make_mut(&mut self.storage_tries).insert(storage_root, storage_trie);
+        self.record(StateChange::StorageTrieReplaced {
+            root: storage_root,
+            previous,
+        });
+
+        // the root is only pinned and the load only counted once the proof loaded, a
+        // rejected one leaves the trie open to the proofs of any block
+        if self.original_root.is_none() {
+            self.record(StateChange::OriginalRoot(None));
+            self.original_root = Some(proof.state_root);
+        }
+        self.usage = usage;
+        Ok(())
+    }
+
+    /// Starts recording the changes made to the state, so they can be undone with
+    /// `revert_to`. Snapshots nest, nothing is copied when one is taken.
+    pub fn snapshot(&mut self) -> SnapshotId {
+        self.snapshots.push(Snapshot {
+            account_trie: self.account_trie.checkpoint(),
+            journal: self.journal.len(),
+            updates: self.updates.as_ref().map_or(0, Vec::len),
+            root: self.root(),
+        });
+        SnapshotId(self.snapshots.len() - 1)
+    }
+
+    /// Undoes every change since the snapshot was taken, restoring the root and the
+    /// loaded nodes as they were then. Snapshots taken after it are dropped.
+    pub fn revert_to(&mut self, id: SnapshotId) -> Result<(), Error> {
+        if id.0 >= self.snapshots.len() {
+            return Err(Error::CheckpointNotFound(id.0));
+        }
+        self.snapshots.truncate(id.0 + 1);
+        let snapshot = self.snapshots.pop().unwrap();
+
+        for change in self.journal.drain(snapshot.journal..).rev() {
+            match change {
+                StateChange::StorageTrieUpdated {
+                    root_old,
+                    root_new,
+                    checkpoint,
+                    replaced,
+                } => {
+                    let mut storage_trie = Arc::make_mut(&mut self.storage_tries)
+                        .remove(&root_new)
+                        .expect("storage trie not present, this should not happen");
+                    if let Some(replaced) = replaced {
+                        Arc::make_mut(&mut self.storage_tries).insert(root_new, replaced);
+                    }
+                    storage_trie.revert_to_checkpoint(checkpoint)?;
+                    Arc::make_mut(&mut self.storage_tries).insert(root_old, storage_trie);
+                }
+                StateChange::StorageTrieReplaced { root, previous } => match previous {
+                    Some(storage_trie) => {
+                        Arc::make_mut(&mut self.storage_tries).insert(root, storage_trie);
+                    }
+                    None => {
+                        Arc::make_mut(&mut self.storage_tries).remove(&root);
+                    }
+                },
+                StateChange::Preimages { address, previous } => match previous {
+                    Some(slots) => {
+                        Arc::make_mut(&mut self.preimages).insert(address, slots);
+                    }
+                    None => {
+                        Arc::make_mut(&mut self.preimages).remove(&address);
+                    }
+                },
+                StateChange::OriginalRoot(root) => self.original_root = root,
+            }
+        }
+        self.account_trie
+            .revert_to_checkpoint(snapshot.account_trie)?;
+        // reverted writes are not proven
+        if let Some(updates) = self.updates.as_mut() {
+            updates.truncate(snapshot.updates);
+        }
+
+        if self.snapshots.is_empty() {
+            // nothing left to revert to, the tries can stop recording
+            self.account_trie.discard_journal();
+            for storage_trie in Arc::make_mut(&mut self.storage_tries).values_mut() {
+                storage_trie.discard_journal();
+            }
+        }
+        debug_assert_eq!(self.root(), snapshot.root);
+        Ok(())
+    }
+
+    /// Records every write from now on as an `MptUpdate`, the roots of consecutive
+    /// updates chain into each other.
+    pub fn enable_journal(&mut self) {
+        self.updates.get_or_insert_with(Vec::new);
+    }
+
+    /// Returns the writes recorded since the journal was enabled or last taken, in
+    /// the order they were applied.
+    pub fn take_journal(&mut self) -> Vec<MptUpdate> {
+        self.updates
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record_update(
+        &mut self,
+        key: MptKey,
+        kind: MptUpdateKind,
+        old_root: H256,
+        old_value: U256,
+        new_value: U256,
+    ) -> Result<(), Error> {
+        if let Some(updates) = self.updates.as_mut() {
+            updates.push(MptUpdate {
+                old_root,
+                new_root: self.account_trie.root().ok_or(Error::RootNotSet)?,
+                key,
+                old_value,
+                new_value,
+                kind,
+            });
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, change: StateChange) {
+        if !self.snapshots.is_empty() {
+            self.journal.push(change);
+        }
+    }
+
+    fn record_preimages(&mut self, address: Address) {
+        if !self.snapshots.is_empty() {
+            let previous = self.preimages.get(&address).cloned();
+            self.journal
+                .push(StateChange::Preimages { address, previous });
+        }
+    }
+
+    /// Checks that the trie was loaded at `parent_header` and that the changes applied
+    /// since give the state root of `header`, the block right after it.
+    pub fn assert_transition(
+        &self,
+        parent_header: &Block<H256>,
+        header: &Block<H256>,
+    ) -> Result<(), Error> {
+        let original_root = self.original_root.ok_or(Error::RootNotSet)?;
+        if original_root != parent_header.state_root {
+            return Err(Error::PreStateRootMismatch(
+                parent_header.state_root,
+                original_root,
+            ));
+        }
+
+        let parent_hash = parent_header
+            .hash
+            .ok_or(Error::IncompleteBlock("parent header hash"))?;
+        if header.parent_hash != parent_hash {
+            return Err(Error::ParentHashMismatch(parent_hash, header.parent_hash));
         }
-        self.storage_tries.insert(proof.storage_hash, storage_trie);
 
+        let root = self.root().ok_or(Error::RootNotSet)?;
+        if root != header.state_root {
+            return Err(Error::PostStateRootMismatch(header.state_root, root));
+        }
         Ok(())
     }
 }
 
+/// Fields of an RLP encoded block header that tie it to the chain and to the tries
+/// it commits to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockHeader {
+    // hash of the encoded header
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub state_root: H256,
+    pub transactions_root: H256,
+    pub receipts_root: H256,
+    pub number: u64,
+    // only set from Shanghai on
+    pub withdrawals_root: Option<H256>,
+}
+
+impl BlockHeader {
+    /// Decodes a header of any hardfork this crate knows the field count of: 15
+    /// before London, 16 with the base fee, 17 with the withdrawals root, 20 with the
+    /// blob gas fields and the beacon root and 21 with the requests hash.
+    pub fn decode(header_rlp: Bytes) -> Result<Self, Error> {
+        let header = Rlp::new(&header_rlp);
+        let fields = header.item_count().decoding("block header")?;
+        if !matches!(fields, 15 | 16 | 17 | 20 | 21) {
+            return Err(Error::UnsupportedHeaderFields(fields));
+        }
+        let hash_at = |i| -> Result<H256, Error> {
+            let field = header.at(i).and_then(|field| field.data());
+            bytes_to_h256(Bytes::from(field.decoding("block header")?.to_vec()))
+        };
+
+        Ok(BlockHeader {
+            hash: H256::from(keccak256(&header_rlp)),
+            parent_hash: hash_at(0)?,
+            state_root: hash_at(3)?,
+            transactions_root: hash_at(4)?,
+            receipts_root: hash_at(5)?,
+            number: header.val_at(8).decoding("block header number")?,
+            withdrawals_root: if fields >= 17 {
+                Some(hash_at(16)?)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+/// State root of the block whose RLP encoded header is `header_rlp`, for checking
+/// that the root proofs are loaded against is the one of a known block (the hash
+/// of `header_rlp` is the block hash).
+pub fn state_root_from_header(header_rlp: Bytes) -> Result<H256, Error> {
+    BlockHeader::decode(header_rlp).map(|header| header.state_root)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{EIP1186ProofResponse, StateTrie, U256};
+    use std::{collections::HashMap, sync::Arc, thread};
+
+    use super::{
+        account_key, empty_root, keccak256, state_root_from_header, storage_key, AccountData,
+        AccountKey, AccountProof, AccountTrie, AccountUpdate, Address, Block, BlockHeader, Bytes,
+        EIP1186ProofResponse, KeyStatus, MptKey, MptUpdateKind, StateTrie, StorageKey, StorageTrie,
+        H256, U256,
+    };
+    use crate::{
+        error::Error,
+        state_root::{
+            account_trie::EMPTY_CODE_HASH,
+            utils::{LimitKind, LoadLimits, NodeData, Trie, WitnessSize},
+        },
+    };
     use ethers::types::StorageProof;
     use ethers_core::utils::hex;
 
@@ -201,4 +1126,1109 @@ mod tests {
             "bf04d56bcfb758b80412e16f9d84ce369ba87534b4226f0d2d41482a2127e811"
         );
     }
+
+    #[test]
+    pub fn test_state_delete_account_1_existing() {
+        let mut trie = StateTrie::new();
+        trie.load_proof(sender_proof()).unwrap();
+        assert_eq!(
+            hex::encode(trie.root().unwrap()),
+            "60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909"
+        );
+
+        let root = trie.delete_account(sender_proof().address).unwrap();
+
+        // sender's slot in the root branch is cleared, other 8 children remain
+        assert_eq!(
+            hex::encode(root),
+            "bf530e799dfd1a9cd27b8d0e2c1f24da4d5bebb8bc19b8661db070eaa0422a29"
+        );
+        assert_eq!(trie.root().unwrap(), root);
+    }
+
+    #[test]
+    pub fn test_state_delete_account_2_created_in_same_block() {
+        let mut trie = StateTrie::new();
+        trie.load_proof(sender_proof()).unwrap();
+        let root_before = trie.root().unwrap();
+        trie.enable_journal();
+
+        // an account created and self destructed within the block is not in the pre
+        // state, keccak(0x..0d) starts with nibble f which is empty in the root branch
+        let created = Address::from_low_u64_be(13);
+        let account_data = AccountData {
+            balance: 1.into(),
+            ..AccountData::empty()
+        };
+        trie.set_account(created, account_data).unwrap();
+        assert_ne!(trie.root().unwrap(), root_before);
+        let root = trie.delete_account(created).unwrap();
+        assert_eq!(root, root_before);
+        assert_eq!(trie.root().unwrap(), root_before);
+
+        let journal = trie.take_journal();
+        assert_eq!(
+            journal.iter().map(|update| update.kind).collect::<Vec<_>>(),
+            vec![MptUpdateKind::Balance, MptUpdateKind::AccountDestructed]
+        );
+        assert_eq!(journal[1].new_root, root_before);
+        assert!(trie.loaded_keys().all(|(address, _)| *address != created));
+
+        // once gone, deleting it again records nothing
+        assert_eq!(trie.delete_account(created).unwrap(), root_before);
+        assert!(trie.take_journal().is_empty());
+    }
+
+    #[test]
+    pub fn test_state_delete_account_3_shared_storage_root() {
+        // the sender and a created account both have the empty storage
+        let sender = sender_proof().address;
+        let created = Address::from_low_u64_be(13);
+        let account_data = AccountData {
+            balance: 1.into(),
+            ..AccountData::empty()
+        };
+        let mut trie = StateTrie::new();
+        trie.load_proof(sender_proof()).unwrap();
+        trie.set_account(created, account_data.clone()).unwrap();
+
+        // the same changes in the other order
+        let mut expected = trie.clone();
+        expected
+            .set_storage_value(created, 1.into(), 1.into())
+            .unwrap();
+        expected.delete_account(sender).unwrap();
+
+        let snapshot = trie.snapshot();
+        let root = trie
+            .apply_updates(&[
+                AccountUpdate {
+                    address: sender,
+                    deleted: true,
+                    ..Default::default()
+                },
+                AccountUpdate {
+                    address: created,
+                    storage: vec![(1.into(), 1.into())],
+                    ..Default::default()
+                },
+            ])
+            .unwrap();
+        assert_eq!(Some(root), expected.root());
+
+        // a write to one account leaves the storage of the other in place
+        trie.revert_to(snapshot).unwrap();
+        trie.set_storage_value(created, 1.into(), 1.into()).unwrap();
+        trie.set_storage_value(sender, 1.into(), 1.into()).unwrap();
+        for address in [created, sender] {
+            let storage_root = trie
+                .account_trie
+                .get_account_data(address)
+                .unwrap()
+                .storage_root;
+            assert_eq!(
+                trie.get_storage_trie(storage_root)
+                    .get_value(1.into())
+                    .unwrap(),
+                1.into()
+            );
+        }
+    }
+
+    // contract from test_state_1
+    fn contract_proof() -> EIP1186ProofResponse {
+        EIP1186ProofResponse {
+            address: "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+                .parse()
+                .unwrap(),
+            account_proof: vec![
+                "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080".parse().unwrap(),
+                "0xf869a03a7a2ee9b4f54ecbf2e04737a19215c0864d20c9a332db61d093e9ec95b2e87ab846f8440180a029cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748ca02e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16".parse().unwrap(),
+            ],
+            balance: "0x0".parse().unwrap(),
+            code_hash: "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16"
+                .parse()
+                .unwrap(),
+            nonce: "0x1".parse().unwrap(),
+            storage_hash: "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+                .parse()
+                .unwrap(),
+            storage_proof: vec![
+                StorageProof {
+                    key: "0x0000000000000000000000000000000000000000000000000000000000000001".parse().unwrap(),
+                    value: "0x2".parse().unwrap(),
+                    proof: vec![
+                        "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc5808080808080a0236e8f61ecde6abfebc6c529441f782f62469d8a2cc47b7aace2c136bd3b1ff08080808080".parse().unwrap(),
+                        "0xe2a0310e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf602".parse().unwrap()
+                    ],
+                },
+                StorageProof {
+                    key: "0x0000000000000000000000000000000000000000000000000000000000000002".parse().unwrap(),
+                    value: "0x4".parse().unwrap(),
+                    proof: vec![
+                        "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc5808080808080a0236e8f61ecde6abfebc6c529441f782f62469d8a2cc47b7aace2c136bd3b1ff08080808080".parse().unwrap(),
+                        "0xe2a0305787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace04".parse().unwrap()
+                    ],
+                },
+            ],
+        }
+    }
+
+    // tx sender from test_state_1
+    fn sender_proof() -> EIP1186ProofResponse {
+        EIP1186ProofResponse {
+            address: "0x3736b9d9d35d8c4f41d98a412fe9211024453575"
+                .parse()
+                .unwrap(),
+            account_proof: vec![
+                "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080".parse().unwrap(),
+                "0xf889a03e19976962fea3751225213669050369b7cd26650bc43815007705e945b5aa57b866f86403a0ffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a0c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470".parse().unwrap(),
+            ],
+            balance: "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82".parse().unwrap(),
+            code_hash: "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+                .parse()
+                .unwrap(),
+            nonce: "0x3".parse().unwrap(),
+            storage_hash: "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+                .parse()
+                .unwrap(),
+            storage_proof: vec![],
+        }
+    }
+
+    // two consecutive headers over the geth --dev state, only the fields checked are
+    // filled in, the transition deletes the sender
+    fn headers() -> (Block<H256>, Block<H256>) {
+        let parent_hash: H256 =
+            "0x4c2fd2dbd8c8e8a4f7a8b3f9c5e534b4352c8b4a36a6c47bcb8de577ebd95ed2"
+                .parse()
+                .unwrap();
+        let parent = Block {
+            hash: Some(parent_hash),
+            number: Some(2.into()),
+            state_root: "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909"
+                .parse()
+                .unwrap(),
+            ..Default::default()
+        };
+        let header = Block {
+            hash: Some(
+                "0x8d1b5a4bd09226a4d4c1d4ccf5b28f1b5a62ec72cbb9ad7f8a8c1892b8d0ccf0"
+                    .parse()
+                    .unwrap(),
+            ),
+            parent_hash,
+            number: Some(3.into()),
+            state_root: "0xbf530e799dfd1a9cd27b8d0e2c1f24da4d5bebb8bc19b8661db070eaa0422a29"
+                .parse()
+                .unwrap(),
+            ..Default::default()
+        };
+        (parent, header)
+    }
+
+    #[test]
+    pub fn test_state_assert_transition_1() {
+        let (parent, header) = headers();
+        let mut trie = StateTrie::for_block(&parent);
+        trie.load_proof(sender_proof()).unwrap();
+        trie.delete_account(sender_proof().address).unwrap();
+
+        trie.assert_transition(&parent, &header).unwrap();
+    }
+
+    #[test]
+    pub fn test_state_assert_transition_2_mismatch() {
+        let (parent, header) = headers();
+        let mut trie = StateTrie::for_block(&parent);
+        trie.load_proof(sender_proof()).unwrap();
+
+        // nothing applied yet, the root is still the parent's
+        assert!(matches!(
+            trie.assert_transition(&parent, &header),
+            Err(Error::PostStateRootMismatch(expected, found))
+                if expected == header.state_root && found == parent.state_root
+        ));
+
+        trie.delete_account(sender_proof().address).unwrap();
+
+        // header two blocks ahead
+        let mut grandchild = header.clone();
+        grandchild.parent_hash = header.hash.unwrap();
+        assert!(matches!(
+            trie.assert_transition(&parent, &grandchild),
+            Err(Error::ParentHashMismatch(_, _))
+        ));
+
+        // trie loaded at another block
+        assert!(matches!(
+            trie.assert_transition(&header, &grandchild),
+            Err(Error::PreStateRootMismatch(expected, found))
+                if expected == header.state_root && found == parent.state_root
+        ));
+    }
+
+    #[test]
+    pub fn test_state_for_block_rejects_other_block() {
+        let (_, header) = headers();
+        // the sender proof is taken at the parent block
+        let mut trie = StateTrie::for_block(&header);
+        assert!(matches!(
+            trie.load_proof(sender_proof()),
+            Err(Error::PreStateRootMismatch(expected, _)) if expected == header.state_root
+        ));
+    }
+
+    #[test]
+    pub fn test_state_load_limits_1() {
+        let root = "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909"
+            .parse()
+            .unwrap();
+
+        // the contract takes three keys and six entries, the sender one more key
+        let mut trie = StateTrie::from_root(root);
+        trie.set_load_limits(LoadLimits {
+            max_keys: 3,
+            ..LoadLimits::unlimited()
+        });
+        trie.load_proof(contract_proof()).unwrap();
+        let loaded = trie.clone();
+        assert!(matches!(
+            trie.load_proof(sender_proof()),
+            Err(Error::LimitExceeded(LimitKind::Keys, 3))
+        ));
+        assert_eq!(trie, loaded);
+
+        // nodes of the account trie and the storage tries add up
+        let mut trie = StateTrie::from_root(root);
+        trie.set_load_limits(LoadLimits {
+            max_nodes: 5,
+            ..LoadLimits::unlimited()
+        });
+        let empty = trie.clone();
+        assert!(matches!(
+            trie.load_proof(contract_proof()),
+            Err(Error::LimitExceeded(LimitKind::Nodes, 5))
+        ));
+        assert_eq!(trie, empty);
+        trie.load_proof(sender_proof()).unwrap();
+
+        // a proof that does not verify takes nothing of the budget
+        let mut trie = StateTrie::from_root(root);
+        trie.set_load_limits(LoadLimits {
+            max_keys: 1,
+            ..LoadLimits::unlimited()
+        });
+        let wrong_balance = EIP1186ProofResponse {
+            balance: 1.into(),
+            ..sender_proof()
+        };
+        assert!(matches!(
+            trie.load_proof(wrong_balance),
+            Err(Error::ValueMismatch(..))
+        ));
+        trie.load_proof(sender_proof()).unwrap();
+    }
+
+    #[test]
+    pub fn test_state_load_proof_rejected_1_root_not_pinned() {
+        // the root taken from a proof that does not verify is not kept
+        let mut trie = StateTrie::new();
+        let rejected = EIP1186ProofResponse {
+            account_proof: vec!["0xc0".parse().unwrap()],
+            ..sender_proof()
+        };
+        assert!(trie.load_proof(rejected).is_err());
+        assert_eq!(trie, StateTrie::new());
+
+        trie.load_proof(sender_proof()).unwrap();
+        assert_eq!(
+            hex::encode(trie.root().unwrap()),
+            "60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909"
+        );
+    }
+
+    #[test]
+    pub fn test_state_witness_size_1() {
+        let mut trie = StateTrie::new();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.load_proof(sender_proof()).unwrap();
+
+        let code = HashMap::from([(contract_proof().code_hash, Bytes::from(vec![0x60; 10]))]);
+        let size = trie.witness_size(&code);
+
+        // root branch of 340 bytes, contract leaf of 107 and sender leaf of 139
+        assert_eq!(
+            size.account_trie,
+            WitnessSize {
+                levels_per_key: vec![2, 2],
+                nodes: 3,
+                rlp_bytes: 340 + 107 + 139,
+                max_depth: 2,
+            }
+        );
+        // contract storage branch of 83 bytes with two leaves of 35, sender storage is empty
+        assert_eq!(
+            size.storage_tries,
+            WitnessSize {
+                levels_per_key: vec![2, 2],
+                nodes: 3,
+                rlp_bytes: 83 + 35 + 35,
+                max_depth: 2,
+            }
+        );
+        assert_eq!(size.accounts, 2);
+        assert_eq!(size.storage_slots, 2);
+        assert_eq!(size.code_bytes, 10);
+    }
+
+    #[test]
+    pub fn test_state_dump_1() {
+        let mut trie = StateTrie::new();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.load_proof(sender_proof()).unwrap();
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+
+        // sorted by hashed address, 0x0e19.. of the sender before 0x2a7a.. of the contract
+        let accounts = trie.accounts().collect::<Vec<_>>();
+        assert_eq!(
+            accounts.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![AccountKey::Address(sender), AccountKey::Address(contract)]
+        );
+        assert_eq!(accounts[0].1.nonce, U256::from(3));
+
+        // the storage trie alone only knows the hashes of the slots
+        let storage_trie = trie.get_storage_trie(contract_proof().storage_hash);
+        let entries = storage_trie.entries().collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    StorageKey::Hashed(storage_key(U256::from(2))),
+                    U256::from(4)
+                ),
+                (
+                    StorageKey::Hashed(storage_key(U256::from(1))),
+                    U256::from(2)
+                ),
+            ]
+        );
+
+        let dump = trie.dump();
+        assert_eq!(dump.root, trie.root());
+        assert_eq!(dump.accounts.len(), 2);
+        assert_eq!(dump.accounts[1].key, AccountKey::Address(contract));
+        assert_eq!(dump.accounts[1].code_hash, contract_proof().code_hash);
+        assert_eq!(
+            dump.accounts[1].storage,
+            vec![
+                (StorageKey::Slot(U256::from(2)), U256::from(4)),
+                (StorageKey::Slot(U256::from(1)), U256::from(2)),
+            ]
+        );
+        assert!(dump.accounts[0].storage.is_empty());
+        let json = serde_json::to_value(&dump).unwrap();
+        assert_eq!(json["accounts"][1]["storage"][1][0]["Slot"], "0x1");
+
+        // an account loaded on the account trie directly has no known preimage
+        let mut trie = StateTrie::new();
+        let proof = sender_proof();
+        trie.account_trie
+            .load_proof(
+                sender,
+                AccountData {
+                    nonce: proof.nonce.as_u64().into(),
+                    balance: proof.balance,
+                    storage_root: proof.storage_hash,
+                    code_hash: proof.code_hash,
+                },
+                proof.account_proof,
+            )
+            .unwrap();
+        assert_eq!(
+            trie.dump().accounts[0].key,
+            AccountKey::Hashed(account_key(sender))
+        );
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    pub fn test_state_snapshot_revert_1() {
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+        let mut trie = StateTrie::new();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.load_proof(sender_proof()).unwrap();
+        // takes only the changes that are not reverted
+        let mut expected = trie.clone();
+
+        trie.set_storage_value(contract, 1.into(), 7.into())
+            .unwrap();
+        expected
+            .set_storage_value(contract, 1.into(), 7.into())
+            .unwrap();
+
+        let outer = trie.snapshot();
+        let root_outer = trie.root();
+        trie.set_storage_value(contract, 2.into(), 0.into())
+            .unwrap();
+        trie.account_trie.set_nonce(sender, 4.into()).unwrap();
+
+        let inner = trie.snapshot();
+        let root_inner = trie.root();
+        trie.delete_account(sender).unwrap();
+        trie.revert_to(inner).unwrap();
+        assert_eq!(trie.root(), root_inner);
+        assert_eq!(
+            trie.account_trie.get_account_data(sender).unwrap().nonce,
+            4.into()
+        );
+
+        trie.set_storage_value(contract, 1.into(), 9.into())
+            .unwrap();
+        trie.snapshot();
+        trie.delete_account(contract).unwrap();
+        trie.revert_to(outer).unwrap();
+        assert_eq!(trie.root(), root_outer);
+        // dropped along with the outer snapshot
+        assert!(trie.revert_to(inner).is_err());
+        assert_eq!(trie, expected);
+
+        trie.account_trie.set_balance(sender, 1.into()).unwrap();
+        expected.account_trie.set_balance(sender, 1.into()).unwrap();
+        assert_eq!(trie.root(), expected.root());
+        let storage_root = trie.account_trie.storage_root_for(contract).unwrap();
+        assert_eq!(
+            trie.get_storage_trie(storage_root)
+                .get_value(2.into())
+                .unwrap(),
+            4.into()
+        );
+    }
+
+    #[test]
+    pub fn test_state_clone_1_diverge() {
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+        let mut trie = StateTrie::new();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.load_proof(sender_proof()).unwrap();
+        let original = trie.clone();
+
+        let mut a = trie.clone();
+        let mut b = trie.clone();
+        a.set_storage_value(contract, 1.into(), 7.into()).unwrap();
+        b.set_storage_value(contract, 1.into(), 9.into()).unwrap();
+        b.delete_account(sender).unwrap();
+
+        // the changes of a clone are not seen by the others
+        assert_eq!(trie, original);
+        assert_ne!(a.root(), trie.root());
+        assert_ne!(a.root(), b.root());
+        let slot = |trie: &StateTrie| {
+            let storage_root = trie.account_trie.storage_root_for(contract).unwrap();
+            trie.get_storage_trie(storage_root)
+                .get_value(1.into())
+                .unwrap()
+        };
+        assert_eq!(slot(&trie), 2.into());
+        assert_eq!(slot(&a), 7.into());
+        assert_eq!(slot(&b), 9.into());
+        assert_eq!(
+            a.account_trie.get_account_data(sender).unwrap().nonce,
+            3.into()
+        );
+        assert_eq!(b.account_trie.contains(sender).unwrap(), KeyStatus::Absent);
+
+        // the same change on a fresh clone gives the same state
+        let mut c = trie.clone();
+        c.set_storage_value(contract, 1.into(), 7.into()).unwrap();
+        assert_eq!(c.root(), a.root());
+    }
+
+    #[test]
+    pub fn test_state_journal_1() {
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+        let mut trie = StateTrie::new();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.load_proof(sender_proof()).unwrap();
+        let root_before = trie.root().unwrap();
+        trie.enable_journal();
+
+        // the sender pays for a call that writes both slots of the contract
+        let mut sender_data = trie.account_trie.get_account_data(sender).unwrap();
+        sender_data.nonce = 4.into();
+        sender_data.balance -= U256::from(21000);
+        trie.set_account(sender, sender_data).unwrap();
+        trie.set_storage_value(contract, 1.into(), 5.into())
+            .unwrap();
+        trie.set_storage_value(contract, 2.into(), 0.into())
+            .unwrap();
+
+        // a reverted write is dropped from the journal
+        let snapshot = trie.snapshot();
+        trie.set_storage_value(contract, 1.into(), 6.into())
+            .unwrap();
+        trie.revert_to(snapshot).unwrap();
+
+        let journal = trie.take_journal();
+        assert_eq!(
+            journal.iter().map(|update| update.kind).collect::<Vec<_>>(),
+            vec![
+                MptUpdateKind::Nonce,
+                MptUpdateKind::Balance,
+                MptUpdateKind::Storage,
+                MptUpdateKind::Storage,
+            ]
+        );
+        assert_eq!(
+            journal[2].key,
+            MptKey::Storage {
+                address: contract,
+                slot: 1.into()
+            }
+        );
+        assert_eq!(journal[2].old_value, 2.into());
+        assert_eq!(journal[3].new_value, U256::zero());
+
+        // the roots chain from the pre state to the current one
+        assert_eq!(journal[0].old_root, root_before);
+        for pair in journal.windows(2) {
+            assert_eq!(pair[0].new_root, pair[1].old_root);
+        }
+        assert_eq!(journal.last().unwrap().new_root, trie.root().unwrap());
+        assert!(trie.take_journal().is_empty());
+    }
+
+    #[test]
+    pub fn test_state_set_storage_value_1_not_loaded() {
+        let contract = contract_proof().address;
+        let mut proof = contract_proof();
+        // slot 2 sits under the root branch of the storage trie next to slot 1
+        proof.storage_proof.truncate(1);
+
+        for journaling in [false, true] {
+            let mut trie = StateTrie::new();
+            trie.load_proof(proof.clone()).unwrap();
+            if journaling {
+                trie.enable_journal();
+            }
+            let root = trie.root();
+            assert!(matches!(
+                trie.set_storage_value(contract, 2.into(), 5.into()),
+                Err(Error::MissingTrieNode(_))
+            ));
+            assert_eq!(trie.root(), root);
+
+            // the storage trie is still there to write the loaded slot
+            trie.set_storage_value(contract, 1.into(), 5.into())
+                .unwrap();
+            let storage_root = trie
+                .account_trie
+                .get_account_data(contract)
+                .unwrap()
+                .storage_root;
+            assert_eq!(
+                trie.get_storage_trie(storage_root)
+                    .get_value(1.into())
+                    .unwrap(),
+                5.into()
+            );
+            if journaling {
+                let journal = trie.take_journal();
+                assert_eq!(journal.len(), 1);
+                assert_eq!(journal[0].old_value, 2.into());
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_state_set_account_1_create() {
+        let one_ether = U256::exp10(18);
+        let created = AccountData {
+            balance: one_ether,
+            ..AccountData::empty()
+        };
+
+        // the path of 0x..0d ends at an empty slot of the root branch
+        let mut trie = StateTrie::new();
+        trie.load_proof(sender_proof()).unwrap();
+        let address = Address::from_low_u64_be(13);
+        trie.set_account(address, created.clone()).unwrap();
+        assert_eq!(
+            hex::encode(trie.root().unwrap()),
+            "56d7574e9687869d01b5c830dd1f08cd7ce33f69114ed016cdb3b1baeb61eb63"
+        );
+        assert_eq!(
+            trie.account_trie.get_account_data(address).unwrap().balance,
+            one_ether
+        );
+        assert!(trie.loaded_keys().any(|(loaded, _)| *loaded == address));
+        // the new account has an empty storage trie that can be written
+        trie.set_storage_value(address, 1.into(), 1.into()).unwrap();
+
+        // the path of 0x..b4 shares two nibbles with the sender, whose leaf goes
+        // down under an extension and a new branch
+        let mut trie = StateTrie::new();
+        trie.load_proof(sender_proof()).unwrap();
+        let address = Address::from_low_u64_be(180);
+        trie.enable_journal();
+        trie.set_account(address, created.clone()).unwrap();
+        assert_eq!(
+            hex::encode(trie.root().unwrap()),
+            "ddf59fd971b0da196229c4e97a4eaa2b9236aaa7c75de977b250a46b4f6e5835"
+        );
+        assert_eq!(
+            trie.account_trie
+                .get_account_data(sender_proof().address)
+                .unwrap()
+                .nonce,
+            3.into()
+        );
+        let journal = trie.take_journal();
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal[0].kind, MptUpdateKind::Balance);
+        assert_eq!(journal[0].old_value, U256::zero());
+
+        // an account left empty is not created
+        let root = trie.root();
+        trie.set_account(Address::from_low_u64_be(13), AccountData::empty())
+            .unwrap();
+        assert_eq!(trie.root(), root);
+    }
+
+    #[test]
+    pub fn test_state_set_account_2_not_loaded() {
+        let mut trie = StateTrie::new();
+        trie.load_proof(sender_proof()).unwrap();
+        let root = trie.root();
+
+        // the path of 0x..01 goes through slot 1 of the root branch, which is not
+        // loaded, so it is not known whether the account exists
+        let address = Address::from_low_u64_be(1);
+        let account_data = AccountData {
+            balance: 1.into(),
+            ..AccountData::empty()
+        };
+        match trie.set_account(address, account_data) {
+            Err(Error::MissingTrieNode(hash)) => assert_eq!(
+                hex::encode(hash),
+                "ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1"
+            ),
+            result => panic!("expected a missing trie node, got {:?}", result),
+        }
+        assert_eq!(trie.root(), root);
+    }
+
+    // eth_getProof for 0x..0d at the root of test_state_1, whose path ends at the
+    // empty slot f of the root branch
+    fn absent_proof() -> EIP1186ProofResponse {
+        EIP1186ProofResponse {
+            address: Address::from_low_u64_be(13),
+            account_proof: sender_proof().account_proof[..1].to_vec(),
+            balance: U256::zero(),
+            code_hash: EMPTY_CODE_HASH,
+            nonce: 0.into(),
+            storage_hash: empty_root(),
+            storage_proof: vec![],
+        }
+    }
+
+    #[test]
+    pub fn test_state_set_account_3_exclusion_proof() {
+        let address = Address::from_low_u64_be(13);
+        let account_data = AccountData {
+            balance: 1.into(),
+            ..AccountData::empty()
+        };
+        // the account created next to the loaded sender
+        let mut expected = StateTrie::new();
+        expected.load_proof(sender_proof()).unwrap();
+        expected.set_account(address, account_data.clone()).unwrap();
+
+        // older geth answers with zero hashes for an address the state does not hold
+        let zero_hashes = EIP1186ProofResponse {
+            code_hash: H256::zero(),
+            storage_hash: H256::zero(),
+            ..absent_proof()
+        };
+        for response in [absent_proof(), zero_hashes] {
+            let root = sender_proof().account_proof[0].clone();
+            let verified = AccountProof::from_eip1186(keccak256(root).into(), response.clone())
+                .verify()
+                .unwrap();
+            assert_eq!(verified.account, AccountData::empty());
+
+            // nothing but the exclusion proof is loaded
+            let mut trie = StateTrie::new();
+            trie.load_proof(response).unwrap();
+            assert_eq!(
+                trie.account_trie.contains(address).unwrap(),
+                KeyStatus::Absent
+            );
+            trie.set_account(address, account_data.clone()).unwrap();
+            assert_eq!(trie.root(), expected.root());
+        }
+
+        // an account claimed to exist is still checked against the proof
+        let claimed = EIP1186ProofResponse {
+            balance: 1.into(),
+            ..absent_proof()
+        };
+        assert!(StateTrie::new().load_proof(claimed).is_err());
+    }
+
+    #[test]
+    pub fn test_state_apply_updates_1() {
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+        let created = Address::from_low_u64_be(13);
+        let mut trie = StateTrie::new();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.load_proof(sender_proof()).unwrap();
+
+        // the same writes one at a time
+        let mut expected = trie.clone();
+        let mut sender_data = expected.account_trie.get_account_data(sender).unwrap();
+        sender_data.nonce = 4.into();
+        expected.set_account(sender, sender_data).unwrap();
+        expected
+            .set_storage_value(contract, 1.into(), 8.into())
+            .unwrap();
+        expected
+            .set_storage_value(contract, 2.into(), 0.into())
+            .unwrap();
+        expected
+            .set_account(
+                created,
+                AccountData {
+                    balance: 1.into(),
+                    ..AccountData::empty()
+                },
+            )
+            .unwrap();
+
+        let root = trie
+            .apply_updates(&[
+                AccountUpdate {
+                    address: sender,
+                    nonce: Some(4.into()),
+                    ..Default::default()
+                },
+                AccountUpdate {
+                    address: contract,
+                    storage: vec![(1.into(), 8.into()), (2.into(), 0.into())],
+                    ..Default::default()
+                },
+                AccountUpdate {
+                    address: created,
+                    balance: Some(1.into()),
+                    ..Default::default()
+                },
+            ])
+            .unwrap();
+        assert_eq!(Some(root), expected.root());
+        assert_eq!(
+            trie.account_trie.get_account_data(sender).unwrap().balance,
+            sender_proof().balance
+        );
+
+        // destructing the created account again
+        trie.apply_updates(&[AccountUpdate {
+            address: created,
+            deleted: true,
+            ..Default::default()
+        }])
+        .unwrap();
+        assert_eq!(
+            trie.account_trie.contains(created).unwrap(),
+            KeyStatus::Absent
+        );
+    }
+
+    #[test]
+    pub fn test_state_export_eip1186_1() {
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+        let mut trie = StateTrie::new();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.load_proof(sender_proof()).unwrap();
+
+        // the loaded proofs come back as they were returned
+        let slots = [U256::from(1), U256::from(2)];
+        assert_eq!(
+            trie.export_eip1186(contract, &slots).unwrap(),
+            contract_proof()
+        );
+        assert_eq!(trie.export_eip1186(sender, &[]).unwrap(), sender_proof());
+
+        // after the changes of test_state_1 the proofs are of the new root
+        let mut sender_data = trie.account_trie.get_account_data(sender).unwrap();
+        sender_data.nonce = 4.into();
+        trie.set_account(sender, sender_data).unwrap();
+        trie.set_storage_value(contract, 1.into(), 8.into())
+            .unwrap();
+        let root = trie.root().unwrap();
+        for (address, slots) in [(contract, &slots[..]), (sender, &[])] {
+            let response = trie.export_eip1186(address, slots).unwrap();
+            // the root node is a new one
+            assert_ne!(response.account_proof[0], contract_proof().account_proof[0]);
+            let verified = AccountProof::from_eip1186(root, response).verify().unwrap();
+            assert_eq!(verified.address, address);
+        }
+        let response = trie.export_eip1186(contract, &slots).unwrap();
+        assert_eq!(response.storage_proof[0].value, 8.into());
+        assert_eq!(response.storage_proof[1].value, 4.into());
+    }
+
+    #[test]
+    pub fn test_state_missing_proofs_1() {
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+        let slot = H256::from_low_u64_be;
+        let targets = vec![(sender, vec![]), (contract, vec![slot(1), slot(2)])];
+
+        // the sender proof brings the root branch of the account trie, not the leaf
+        // of the contract
+        let mut trie = StateTrie::new();
+        trie.load_proof(sender_proof()).unwrap();
+        assert_eq!(
+            trie.missing_proofs(&targets).unwrap(),
+            vec![(contract, vec![slot(1), slot(2)])]
+        );
+
+        // the contract with slot 1 only, slot 2 is the leaf next to it
+        let mut partial = contract_proof();
+        partial.storage_proof.truncate(1);
+        trie.load_proof(partial).unwrap();
+        assert_eq!(
+            trie.missing_proofs(&targets).unwrap(),
+            vec![(contract, vec![slot(2)])]
+        );
+
+        trie.load_proof(contract_proof()).unwrap();
+        assert!(trie.missing_proofs(&targets).unwrap().is_empty());
+        // a slot of an account without storage
+        assert!(trie
+            .missing_proofs(&[(sender, vec![slot(1)])])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_state_reset_1_next_block() {
+        let (parent, header) = headers();
+        let contract = contract_proof().address;
+        let sender = sender_proof().address;
+        let slots = [U256::from(1), U256::from(2)];
+
+        // the contract at the second block, where the sender is gone
+        let mut next = StateTrie::for_block(&parent);
+        next.load_proof(contract_proof()).unwrap();
+        next.load_proof(sender_proof()).unwrap();
+        next.delete_account(sender).unwrap();
+        assert_eq!(next.root(), Some(header.state_root));
+        let contract_next = next.export_eip1186(contract, &slots).unwrap();
+
+        // the contract alone takes the three keys
+        let mut trie = StateTrie::for_block(&parent);
+        trie.set_load_limits(LoadLimits {
+            max_keys: 3,
+            ..LoadLimits::unlimited()
+        });
+        trie.enable_journal();
+        trie.load_proof(contract_proof()).unwrap();
+        trie.set_storage_value(contract, 1.into(), 8.into())
+            .unwrap();
+        trie.snapshot();
+
+        trie.reset_for_block(&header);
+        assert_eq!(trie.root(), Some(header.state_root));
+        assert!(trie.take_journal().is_empty());
+        assert!(trie.dump().accounts.is_empty());
+        assert!(trie.account_trie.get_account_data(contract).is_err());
+        assert!(matches!(
+            trie.load_proof(contract_proof()),
+            Err(Error::PreStateRootMismatch(expected, _)) if expected == header.state_root
+        ));
+
+        // the proofs of the second block load within the same limits, the slot
+        // written at the first one is back to what the second block holds
+        trie.load_proof(contract_next).unwrap();
+        let storage_root = trie.account_trie.storage_root_for(contract).unwrap();
+        assert_eq!(
+            trie.get_storage_trie(storage_root)
+                .get_value(1.into())
+                .unwrap(),
+            U256::from(2)
+        );
+        trie.set_storage_value(contract, 2.into(), 8.into())
+            .unwrap();
+        assert_eq!(trie.take_journal().len(), 1);
+    }
+
+    #[test]
+    pub fn test_state_send_sync() {
+        assert_send_sync::<StateTrie>();
+        assert_send_sync::<AccountTrie>();
+        assert_send_sync::<StorageTrie>();
+        assert_send_sync::<Trie>();
+        assert_send_sync::<NodeData>();
+    }
+
+    #[test]
+    pub fn test_state_concurrent_readers() {
+        let mut trie = StateTrie::new();
+
+        trie.load_proof(contract_proof()).unwrap();
+
+        let trie = Arc::new(trie);
+        let handles = (0..4)
+            .map(|_| {
+                let trie = Arc::clone(&trie);
+                thread::spawn(move || {
+                    let account = trie
+                        .account_trie
+                        .get_account_data(
+                            "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+                                .parse()
+                                .unwrap(),
+                        )
+                        .unwrap();
+                    assert_eq!(account.nonce, U256::from(1));
+
+                    let storage_trie = trie.get_storage_trie(account.storage_root);
+                    assert_eq!(
+                        storage_trie.get_value(U256::from(1)).unwrap(),
+                        U256::from(2)
+                    );
+                    assert_eq!(
+                        storage_trie.get_value(U256::from(2)).unwrap(),
+                        U256::from(4)
+                    );
+                    trie.root().unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            assert_eq!(
+                hex::encode(handle.join().unwrap()),
+                "60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_state_root_from_header_1() {
+        // mainnet genesis block
+        let header: Bytes = "0xf90214a00000000000000000000000000000000000000000000000000000000000000000a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347940000000000000000000000000000000000000000a0d7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421b9010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000850400000000808213888080a011bbe8db4e347b4e8c937c1c8370e4b5ed33adb3db69cbdb7a38e1e50b1b82faa00000000000000000000000000000000000000000000000000000000000000000880000000000000042"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            H256::from(keccak256(&header)),
+            "0xd4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            state_root_from_header(header).unwrap(),
+            "0xd7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544"
+                .parse()
+                .unwrap()
+        );
+
+        // not a list, and a list too short to hold the state root
+        assert!(state_root_from_header("0x820102".parse().unwrap()).is_err());
+        assert!(state_root_from_header("0xc3010203".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    pub fn test_state_trie_from_header_1_london() {
+        // sepolia and holesky genesis blocks, both with a base fee
+        for (header, hash, state_root) in [
+            (
+                "0xf9021da00000000000000000000000000000000000000000000000000000000000000000a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347940000000000000000000000000000000000000000a05eb6e371a698b8d68f665192350ffcecbbbf322916f4b51bd79bb6887da3f494a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421b901000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000083020000808401c9c38080846159af19a05365706f6c69612c20417468656e732c204174746963612c2047726565636521a00000000000000000000000000000000000000000000000000000000000000000880000000000000000843b9aca00",
+                "0x25a5cc106eea7138acab33231d7160d69cb777ee0c2c553fcddf5138993e6dd9",
+                "0x5eb6e371a698b8d68f665192350ffcecbbbf322916f4b51bd79bb6887da3f494",
+            ),
+            (
+                "0xf901faa00000000000000000000000000000000000000000000000000000000000000000a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347940000000000000000000000000000000000000000a069d8c9d72f6fa4ad42d4702b433707212f90db395eb54dc20bc85de253788783a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421b9010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000018084017d784080846515699480a00000000000000000000000000000000000000000000000000000000000000000880000000000001234843b9aca00",
+                "0xb5f7f912443c940f21fd611f12828d75b534364ed9e95ca4e307729a4661bde4",
+                "0x69d8c9d72f6fa4ad42d4702b433707212f90db395eb54dc20bc85de253788783",
+            ),
+        ] {
+            let header: Bytes = header.parse().unwrap();
+            let (trie, header_hash) = StateTrie::from_header(header.clone()).unwrap();
+            assert_eq!(header_hash, hash.parse().unwrap());
+            assert_eq!(trie.root(), Some(state_root.parse().unwrap()));
+
+            let header = BlockHeader::decode(header).unwrap();
+            assert_eq!(header.number, 0);
+            assert_eq!(header.parent_hash, H256::zero());
+            assert_eq!(header.transactions_root, "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421".parse().unwrap());
+            assert_eq!(header.receipts_root, "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421".parse().unwrap());
+            assert_eq!(header.withdrawals_root, None);
+        }
+    }
+
+    #[test]
+    pub fn test_state_trie_from_header_2_shanghai_cancun() {
+        // made up headers on top of the holesky genesis, with the 17 fields of
+        // Shanghai and then the 20 of Cancun
+        let shanghai: Bytes = "0xf90217a0b5f7f912443c940f21fd611f12828d75b534364ed9e95ca4e307729a4661bde4a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347940000000000000000000000000000000000000000a01111111111111111111111111111111111111111111111111111111111111111a02222222222222222222222222222222222222222222222222222222222222222a03333333333333333333333333333333333333333333333333333333333333333b9010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000800184017d78408084651569a080a0000000000000000000000000000000000000000000000000000000000000000088000000000000000007a04444444444444444444444444444444444444444444444444444444444444444"
+            .parse()
+            .unwrap();
+        let cancun: Bytes = "0xf9023da05aca7e3817ba47287400902338e5da1e43fd8938c512b01a94bb4e1c0886d244a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347940000000000000000000000000000000000000000a01111111111111111111111111111111111111111111111111111111111111111a02222222222222222222222222222222222222222222222222222222222222222a03333333333333333333333333333333333333333333333333333333333333333b9010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000800284017d78408084651569ac80a0000000000000000000000000000000000000000000000000000000000000000088000000000000000007a044444444444444444444444444444444444444444444444444444444444444448302000080a05555555555555555555555555555555555555555555555555555555555555555"
+            .parse()
+            .unwrap();
+
+        let shanghai = BlockHeader::decode(shanghai).unwrap();
+        assert_eq!(
+            shanghai.parent_hash,
+            "0xb5f7f912443c940f21fd611f12828d75b534364ed9e95ca4e307729a4661bde4"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(shanghai.number, 1);
+        assert_eq!(shanghai.state_root, H256::repeat_byte(0x11));
+        assert_eq!(shanghai.transactions_root, H256::repeat_byte(0x22));
+        assert_eq!(shanghai.receipts_root, H256::repeat_byte(0x33));
+        assert_eq!(shanghai.withdrawals_root, Some(H256::repeat_byte(0x44)));
+
+        let (trie, hash) = StateTrie::from_header(cancun.clone()).unwrap();
+        assert_eq!(trie.root(), Some(H256::repeat_byte(0x11)));
+        let cancun = BlockHeader::decode(cancun).unwrap();
+        assert_eq!(cancun.hash, hash);
+        assert_eq!(cancun.parent_hash, shanghai.hash);
+        assert_eq!(cancun.number, 2);
+        assert_eq!(cancun.withdrawals_root, Some(H256::repeat_byte(0x44)));
+    }
+
+    #[test]
+    pub fn test_state_trie_from_header_3_malformed() {
+        // the Shanghai header of test_state_trie_from_header_2_shanghai_cancun with a
+        // field appended
+        let header: Bytes = "0xf90218a0b5f7f912443c940f21fd611f12828d75b534364ed9e95ca4e307729a4661bde4a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347940000000000000000000000000000000000000000a01111111111111111111111111111111111111111111111111111111111111111a02222222222222222222222222222222222222222222222222222222222222222a03333333333333333333333333333333333333333333333333333333333333333b9010000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000800184017d78408084651569a080a0000000000000000000000000000000000000000000000000000000000000000088000000000000000007a0444444444444444444444444444444444444444444444444444444444444444480"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            StateTrie::from_header(header),
+            Err(Error::UnsupportedHeaderFields(18))
+        ));
+
+        // not a list
+        assert!(matches!(
+            StateTrie::from_header("0x820102".parse().unwrap()),
+            Err(Error::RlpDecoderError("block header", _))
+        ));
+        // a list of 15 empty fields
+        assert!(matches!(
+            BlockHeader::decode("0xcf808080808080808080808080808080".parse().unwrap()),
+            Err(Error::InvalidHashLength(_))
+        ));
+    }
 }
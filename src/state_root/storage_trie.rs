@@ -1,13 +1,36 @@
-use super::utils::{Nibbles, Trie};
-use crate::error::Error;
+use super::utils::{
+    bytes_to_h256, check_canonical, storage_key, KeyStatus, LeafKind, LoadLimits, Nibbles,
+    PathStatus, Trie, TrieStats, WitnessSize,
+};
+use crate::error::{DecodeContext, Error};
 use ethers::{
     prelude::EthDisplay,
-    types::{BigEndianHash, Bytes, H256, U256},
+    types::{Bytes, H256, U256},
+    utils::{rlp, rlp::Rlp},
 };
+use serde::Serialize;
 
 #[derive(Debug, Clone, EthDisplay, PartialEq)]
 pub struct StorageTrie(Trie);
 
+/// Key of a loaded storage slot, the slot itself when its preimage is known and
+/// the keccak256 of it otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum StorageKey {
+    Slot(U256),
+    Hashed(H256),
+}
+
+impl StorageKey {
+    // the key of the slot in the storage trie
+    pub fn hashed(&self) -> H256 {
+        match self {
+            StorageKey::Slot(slot) => storage_key(*slot),
+            StorageKey::Hashed(hash) => *hash,
+        }
+    }
+}
+
 impl StorageTrie {
     pub fn new() -> Self {
         StorageTrie(Trie::new())
@@ -17,6 +40,14 @@ impl StorageTrie {
         StorageTrie(Trie::from_root(root))
     }
 
+    /// Same as `from_root`, with the leaves of loaded proofs checked to be of
+    /// `expect`. `LeafKind::Raw` checks nothing.
+    pub fn from_root_expecting(root: H256, expect: LeafKind) -> Self {
+        let mut trie = Trie::from_root(root);
+        trie.set_leaf_kind(expect);
+        StorageTrie(trie)
+    }
+
     pub fn set_root(&mut self, root: H256) -> Result<(), Error> {
         self.0.set_root(root)
     }
@@ -25,31 +56,223 @@ impl StorageTrie {
         self.0.root
     }
 
+    pub fn witness_size(&self) -> WitnessSize {
+        self.0.witness_size()
+    }
+
+    pub fn stats(&self) -> TrieStats {
+        self.0.stats()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.0.node_count()
+    }
+
+    pub fn set_load_limits(&mut self, limits: LoadLimits) {
+        self.0.set_load_limits(limits);
+    }
+
+    pub fn checkpoint(&mut self) -> usize {
+        self.0.checkpoint()
+    }
+
+    pub fn revert_to_checkpoint(&mut self, checkpoint: usize) -> Result<(), Error> {
+        self.0.revert_to_checkpoint(checkpoint)
+    }
+
+    pub fn discard_journal(&mut self) {
+        self.0.discard_journal()
+    }
+
     pub fn get_value(&self, key: U256) -> Result<U256, Error> {
         let path = Nibbles::from_uint(key)?;
-        let bytes = self.0.get_value(path)?;
-        Ok(U256::from_big_endian(bytes.to_vec().as_slice()))
+        decode_value(self.0.get_value(path)?)
+    }
+
+    // Absent only when the loaded nodes prove that the slot is not set
+    pub fn contains(&self, key: U256) -> Result<KeyStatus, Error> {
+        self.0.contains(Nibbles::from_uint(key)?)
+    }
+
+    pub fn is_path_loaded(&self, key: U256) -> Result<PathStatus, Error> {
+        self.0.is_path_loaded(Nibbles::from_uint(key)?)
     }
 
+    /// Setting a slot to zero removes it from the trie, as the EVM does.
     pub fn set_value(&mut self, key: U256, new_value: U256) -> Result<(), Error> {
         let path = Nibbles::from_uint(key)?;
-        self.0.set_value(path, u256_to_bytes(new_value))
+        if new_value.is_zero() {
+            self.0.remove_value(path)?;
+            Ok(())
+        } else {
+            self.0.set_value(path, encode_value(new_value))
+        }
+    }
+
+    // proof of the slot at the current root, see Trie::prove
+    pub fn prove(&self, key: U256) -> Result<Vec<Bytes>, Error> {
+        self.0.prove(Nibbles::from_uint(key)?)
     }
 
+    /// Slots whose full path is loaded with their values, sorted by hashed key. The
+    /// trie only knows the hashes of the slots, see `StateTrie::dump` for keys with
+    /// their preimages. Leaves that do not hold a canonical word are left out,
+    /// `get_value` errors on them.
+    pub fn entries(&self) -> impl Iterator<Item = (StorageKey, U256)> {
+        self.0.leaves().into_iter().filter_map(|(key, value)| {
+            Some((
+                StorageKey::Hashed(bytes_to_h256(key).ok()?),
+                decode_value(value).ok()?,
+            ))
+        })
+    }
+
+    /// `value` is the plain word returned by `eth_getProof`, zero for a slot the
+    /// proof excludes.
     pub fn load_proof(&mut self, key: U256, value: U256, proof: Vec<Bytes>) -> Result<(), Error> {
         let path = Nibbles::from_uint(key)?;
-        self.0.load_proof(path, u256_to_bytes(value), proof)
+        self.0.load_proof(path, encode_value(value), proof)
     }
 }
 
-fn u256_to_bytes(value: U256) -> Bytes {
-    let mut vec = H256::from_uint(&value).as_bytes().to_vec();
-    loop {
-        if vec[0] == 0 {
-            vec.remove(0);
-        } else {
-            break;
-        }
+// leaves hold the RLP of the word with leading zeros stripped, zero is never stored
+fn encode_value(value: U256) -> Bytes {
+    if value.is_zero() {
+        return Bytes::new();
+    }
+    Bytes::from(rlp::encode(&value).to_vec())
+}
+
+pub(super) fn decode_value(raw: Bytes) -> Result<U256, Error> {
+    // an absent slot resolves to the empty value
+    if raw.is_empty() || raw.as_ref() == [0x00] {
+        return Ok(U256::zero());
+    }
+    let data = Rlp::new(&raw).data().decoding("storage value")?;
+    if data.len() > 32 {
+        return Err(Error::NonCanonicalRlp(0));
+    }
+    let value = U256::from_big_endian(data);
+    // leading zeros or a redundant prefix do not survive the re-encoding
+    check_canonical(&raw, &encode_value(value))?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_value, Bytes, Error, LeafKind, StorageTrie, U256};
+    use crate::state_root::utils::EMPTY_ROOT;
+    use ethers_core::utils::hex;
+
+    const BRANCH: &str = "0xf85180808080a05cec288029f80518906c03ad962a0d47ecdf98680e3d85558885e7f3e7ac4bee808080808080a0f4984a11f61a2921456141df88de6e1a710d28681b91af794c5a721e47839cd78080808080";
+
+    #[test]
+    pub fn test_storage_trie_value_codec_1() {
+        // slot 1 holds 0x01, slot 2 holds a word using all 32 bytes
+        let mut trie = StorageTrie::from_root(
+            "0xda5afc4fb59b393c6cbbb720ee7812ef070b0b23a10e0b8c44a6885f6b41b8df"
+                .parse()
+                .unwrap(),
+        );
+        trie.load_proof(
+            U256::from(1),
+            U256::from(1),
+            vec![
+                BRANCH.parse().unwrap(),
+                "0xe2a0310e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf601"
+                    .parse()
+                    .unwrap(),
+            ],
+        )
+        .unwrap();
+        trie.load_proof(
+            U256::from(2),
+            U256::MAX,
+            vec![
+                BRANCH.parse().unwrap(),
+                "0xf843a0305787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5acea1a0ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+                    .parse()
+                    .unwrap(),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(trie.get_value(U256::from(1)).unwrap(), U256::from(1));
+        assert_eq!(trie.get_value(U256::from(2)).unwrap(), U256::MAX);
+    }
+
+    #[test]
+    pub fn test_storage_trie_leaf_kind_1() {
+        let root = "0xda5afc4fb59b393c6cbbb720ee7812ef070b0b23a10e0b8c44a6885f6b41b8df"
+            .parse()
+            .unwrap();
+        let mut trie = StorageTrie::from_root_expecting(root, LeafKind::StorageValue);
+        trie.load_proof(
+            U256::from(1),
+            U256::from(1),
+            vec![
+                BRANCH.parse().unwrap(),
+                "0xe2a0310e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf601"
+                    .parse()
+                    .unwrap(),
+            ],
+        )
+        .unwrap();
+
+        // a leaf holding an account is not a storage value
+        let mut trie = StorageTrie::from_root_expecting(
+            "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909"
+                .parse()
+                .unwrap(),
+            LeafKind::StorageValue,
+        );
+        assert!(matches!(
+            trie.load_proof(
+                U256::from(1),
+                U256::from(1),
+                vec![
+                    "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080".parse().unwrap(),
+                    "0xf869a03a7a2ee9b4f54ecbf2e04737a19215c0864d20c9a332db61d093e9ec95b2e87ab846f8440180a029cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748ca02e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16".parse().unwrap(),
+                ],
+            ),
+            Err(Error::UnexpectedLeafShape(LeafKind::StorageValue, _))
+        ));
+    }
+
+    #[test]
+    pub fn test_storage_trie_value_codec_2_set_from_empty() {
+        let mut trie = StorageTrie::from_root(EMPTY_ROOT);
+        trie.set_value(U256::from(1), U256::from(1)).unwrap();
+        trie.set_value(U256::from(2), U256::MAX).unwrap();
+        assert_eq!(
+            hex::encode(trie.root().unwrap()),
+            "da5afc4fb59b393c6cbbb720ee7812ef070b0b23a10e0b8c44a6885f6b41b8df"
+        );
+
+        // zero deletes the slot
+        trie.set_value(U256::from(2), U256::zero()).unwrap();
+        trie.set_value(U256::from(1), U256::zero()).unwrap();
+        assert_eq!(trie.root().unwrap(), EMPTY_ROOT);
+    }
+
+    #[test]
+    pub fn test_storage_trie_value_codec_3_non_canonical() {
+        // 0x01 with a leading zero byte
+        assert!(matches!(
+            decode_value("0x820001".parse::<Bytes>().unwrap()),
+            Err(Error::NonCanonicalRlp(0))
+        ));
+        // 0x01 behind a string prefix
+        assert!(matches!(
+            decode_value("0x8101".parse::<Bytes>().unwrap()),
+            Err(Error::NonCanonicalRlp(0))
+        ));
+        // 33 bytes
+        let mut raw = vec![0xa1];
+        raw.extend([0xff; 33]);
+        assert!(matches!(
+            decode_value(Bytes::from(raw)),
+            Err(Error::NonCanonicalRlp(0))
+        ));
     }
-    Bytes::from(vec)
 }
@@ -0,0 +1,168 @@
+use ethers::{
+    types::{BigEndianHash, Bytes, H256, U256},
+    utils::hex,
+};
+
+use crate::error::{DecodeContext, Error};
+
+// Conversions between the words, hashes and byte strings that keys and values are
+// built from. These work on the ethers types used by the tries, the ones in
+// `types::zkevm_types` come from another ethers-core version.
+
+/// Big endian bytes of `value`, left padded to 32 bytes as in storage keys.
+pub fn pad32(value: U256) -> [u8; 32] {
+    H256::from_uint(&value).to_fixed_bytes()
+}
+
+/// Drops the leading zero bytes, as RLP encodes integers.
+pub fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// Parses hex with or without the `0x` prefix.
+pub fn parse_hex_bytes(input: &str) -> Result<Bytes, Error> {
+    let input = input.strip_prefix("0x").unwrap_or(input);
+    Ok(Bytes::from(hex::decode(input).decoding("hex string")?))
+}
+
+/// Errors with `Error::NonCanonicalRlp` at the first byte where `raw` differs from
+/// its `canonical` re-encoding.
+pub fn check_canonical(raw: &[u8], canonical: &[u8]) -> Result<(), Error> {
+    if raw != canonical {
+        let offset = raw
+            .iter()
+            .zip(canonical.iter())
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| raw.len().min(canonical.len()));
+        return Err(Error::NonCanonicalRlp(offset));
+    }
+    Ok(())
+}
+
+pub fn bytes_to_h256(bytes: Bytes) -> Result<H256, Error> {
+    if bytes.len() != 32 {
+        return Err(Error::InvalidHashLength(bytes));
+    }
+    Ok(H256::from_slice(&bytes))
+}
+
+pub fn h256_to_bytes(hash: H256) -> Bytes {
+    Bytes::from(hash.as_bytes().to_vec())
+}
+
+/// Packs nibbles two to a byte, high nibble first. A full key is whole bytes, so
+/// an odd count is an error rather than a padded or dropped nibble.
+pub fn nibbles_to_bytes(nibbles: &[u8]) -> Result<Bytes, Error> {
+    if nibbles.len() % 2 != 0 {
+        return Err(Error::OddNibbleCount(nibbles.len()));
+    }
+    nibbles
+        .chunks(2)
+        .map(|pair| match pair.iter().find(|nibble| **nibble > 0xf) {
+            Some(nibble) => Err(Error::InvalidNibble(*nibble)),
+            None => Ok(pair[0] << 4 | pair[1]),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Bytes::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bytes_to_h256, h256_to_bytes, nibbles_to_bytes, pad32, parse_hex_bytes, trim_leading_zeros,
+        Bytes, Error, H256, U256,
+    };
+
+    #[test]
+    pub fn test_pad32_1() {
+        let padded = pad32(U256::from(0x0102));
+        assert_eq!(padded[..30], [0; 30]);
+        assert_eq!(padded[30..], [0x01, 0x02]);
+        assert_eq!(trim_leading_zeros(&padded), [0x01, 0x02]);
+        assert_eq!(trim_leading_zeros(&pad32(U256::zero())), [0u8; 0]);
+    }
+
+    #[test]
+    pub fn test_parse_hex_bytes_1() {
+        let expected = Bytes::from(vec![0xab, 0xcd]);
+        assert_eq!(parse_hex_bytes("0xabcd").unwrap(), expected);
+        assert_eq!(parse_hex_bytes("abcd").unwrap(), expected);
+        assert_eq!(parse_hex_bytes("0x").unwrap(), Bytes::new());
+        assert!(matches!(
+            parse_hex_bytes("0xabc"),
+            Err(Error::HexError("hex string", _))
+        ));
+        assert!(matches!(
+            parse_hex_bytes("0xzz"),
+            Err(Error::HexError("hex string", _))
+        ));
+    }
+
+    #[test]
+    pub fn test_bytes_to_h256_1() {
+        let hash = H256::repeat_byte(0x11);
+        assert_eq!(bytes_to_h256(h256_to_bytes(hash)).unwrap(), hash);
+        assert!(bytes_to_h256(Bytes::from(vec![0x11; 31])).is_err());
+        assert!(bytes_to_h256(Bytes::from(vec![0x11; 33])).is_err());
+    }
+
+    #[test]
+    pub fn test_nibbles_to_bytes_1() {
+        assert_eq!(
+            nibbles_to_bytes(&[0x1, 0x2, 0xa, 0xf]).unwrap(),
+            Bytes::from(vec![0x12, 0xaf])
+        );
+        assert_eq!(nibbles_to_bytes(&[]).unwrap(), Bytes::new());
+    }
+
+    #[test]
+    pub fn test_nibbles_to_bytes_2_odd() {
+        assert!(matches!(
+            nibbles_to_bytes(&[0x1, 0x2, 0x3]),
+            Err(Error::OddNibbleCount(3))
+        ));
+        assert!(matches!(
+            nibbles_to_bytes(&[0x1, 0x10]),
+            Err(Error::InvalidNibble(0x10))
+        ));
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod proptests {
+    use proptest::{collection::vec, prelude::*};
+
+    use super::{bytes_to_h256, h256_to_bytes, pad32, trim_leading_zeros, Bytes, H256, U256};
+
+    proptest! {
+        #[test]
+        fn test_pad32_trim_round_trip(limbs in any::<[u64; 4]>()) {
+            let value = U256(limbs);
+            let padded = pad32(value);
+            let trimmed = trim_leading_zeros(&padded);
+            prop_assert!(trimmed.first() != Some(&0));
+            prop_assert_eq!(U256::from_big_endian(trimmed), value);
+            prop_assert_eq!(pad32(U256::from_big_endian(trimmed)), padded);
+        }
+
+        #[test]
+        fn test_bytes_to_h256_length(bytes in vec(any::<u8>(), 0..64)) {
+            let result = bytes_to_h256(Bytes::from(bytes.clone()));
+            if bytes.len() == 32 {
+                prop_assert_eq!(h256_to_bytes(result.unwrap()), Bytes::from(bytes));
+            } else {
+                prop_assert!(result.is_err());
+            }
+        }
+
+        #[test]
+        fn test_h256_round_trip(bytes in any::<[u8; 32]>()) {
+            let hash = H256(bytes);
+            prop_assert_eq!(bytes_to_h256(h256_to_bytes(hash)).unwrap(), hash);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use std::fmt;
+
+use ethers::{types::H256, utils::keccak256};
+
+/// Hash backend used by the trie for node hashing, so that targets with an
+/// accelerated keccak implementation can plug it in.
+pub trait Keccak: fmt::Debug {
+    fn keccak256(&self, data: &[u8]) -> H256;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DefaultKeccak;
+
+impl Keccak for DefaultKeccak {
+    fn keccak256(&self, data: &[u8]) -> H256 {
+        H256::from(keccak256(data))
+    }
+}
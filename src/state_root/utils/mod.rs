@@ -1,5 +1,14 @@
+pub mod conversions;
+pub mod keccak;
 pub mod nibbles;
+mod node_store;
+#[cfg(feature = "test-utils")]
+pub mod reference_trie;
+pub mod rlp_decoder;
 pub mod trie;
 
+pub use conversions::*;
+pub use keccak::*;
 pub use nibbles::*;
+pub use rlp_decoder::*;
 pub use trie::*;
@@ -1,13 +1,17 @@
 use std::fmt;
 
 use ethers::{
-    types::{Address, BigEndianHash, Bytes, H256, U256},
-    utils::keccak256,
+    types::{Address, Bytes, H256, U256},
+    utils::{keccak256, rlp},
 };
 
+use super::conversions::{h256_to_bytes, pad32, parse_hex_bytes};
 use crate::error::Error;
 
-#[derive(Clone, PartialEq)]
+/// Path in a trie, one nibble per element. Paths are ordered nibble by nibble, so
+/// a path sorts right after its prefixes and odd length paths are not compared by
+/// their zero padded raw bytes.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Nibbles(Vec<u8>);
 
 fn u8_to_u4_vec(u8_vec: Vec<u8>) -> Vec<u8> {
@@ -19,21 +23,32 @@ fn u8_to_u4_vec(u8_vec: Vec<u8>) -> Vec<u8> {
     u4_vec
 }
 
+/// Key of an account in the state trie, keccak256 of the 20 address bytes.
+pub fn account_key(address: Address) -> H256 {
+    H256::from(keccak256(address.as_bytes()))
+}
+
+/// Key of a slot in a storage trie, keccak256 of the slot as a 32 byte big endian
+/// word.
+pub fn storage_key(slot: U256) -> H256 {
+    H256::from(keccak256(pad32(slot)))
+}
+
 impl Nibbles {
     pub fn from_address(address: Address) -> Result<Nibbles, Error> {
-        Ok(Self::from_raw_path(Bytes::from(
-            H256::from(keccak256(address)).as_bytes().to_vec(),
-        )))
+        Ok(Self::from_raw_path(h256_to_bytes(account_key(address))))
     }
 
     pub fn from_uint(uint: U256) -> Result<Nibbles, Error> {
-        Ok(Self::from_raw_path(Bytes::from(
-            H256::from(keccak256(Bytes::from(
-                H256::from_uint(&uint).as_bytes().to_vec(),
-            )))
-            .as_bytes()
-            .to_vec(),
-        )))
+        Ok(Self::from_raw_path(h256_to_bytes(storage_key(uint))))
+    }
+
+    /// Key of the entry at `index` of a transactions, receipts or withdrawals trie,
+    /// the RLP of the index and not its hash. It is a single byte up to 127 (0x80
+    /// for 0) and longer past that, RLP strings carrying their length no such key
+    /// is a prefix of another.
+    pub fn from_index(index: u64) -> Nibbles {
+        Self::from_raw_path(Bytes::from(rlp::encode(&index).to_vec()))
     }
 
     pub fn from_raw_path_str(str: &str) -> Self {
@@ -41,7 +56,7 @@ impl Nibbles {
     }
 
     pub fn from_encoded_path_str(str: &str) -> Result<Self, Error> {
-        Self::from_encoded_path(str.parse::<Bytes>().unwrap())
+        Self::from_encoded_path(parse_hex_bytes(str)?)
     }
 
     pub fn from_raw_path(bytes: Bytes) -> Self {
@@ -51,7 +66,7 @@ impl Nibbles {
     pub fn from_u4_vec(nibbles: Vec<u8>) -> Result<Self, Error> {
         for nibble in nibbles.iter() {
             if *nibble > 0xF {
-                return Err(Error::InternalError("cannot be more than 4 bits"));
+                return Err(Error::InvalidNibble(*nibble));
             }
         }
         Ok(Self(nibbles))
@@ -62,6 +77,9 @@ impl Nibbles {
         Ok(val)
     }
     pub fn from_encoded_path_with_terminator(bytes: Bytes) -> Result<(Self, bool), Error> {
+        if bytes.is_empty() {
+            return Err(Error::InvalidEncodedPath(bytes));
+        }
         let mut u4_vec = u8_to_u4_vec(bytes.to_vec());
 
         let first = u4_vec[0];
@@ -70,12 +88,12 @@ impl Nibbles {
             u4_vec.remove(0);
         } else if first == 0 || first == 2 {
             if second != 0 {
-                return Err(Error::InternalError("bad second nibble"));
+                return Err(Error::InvalidEncodedPath(bytes));
             }
             u4_vec.remove(1);
             u4_vec.remove(0);
         } else {
-            return Err(Error::InternalError("bad first nibble"));
+            return Err(Error::InvalidEncodedPath(bytes));
         }
         Ok((Self(u4_vec), first >= 2))
     }
@@ -119,7 +137,7 @@ impl Nibbles {
 
     pub fn slice(&self, from: usize) -> Result<Self, Error> {
         if self.0.len() < from {
-            return Err(Error::InternalError("slice from is larger than len"));
+            return Err(Error::SliceOutOfRange(from, self.0.len()));
         }
         let mut nibbles_vec = Vec::new();
         for i in from..self.0.len() {
@@ -131,6 +149,17 @@ impl Nibbles {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn starts_with(&self, prefix: &Nibbles) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    // the rest of the path after `prefix`, None if it does not start with it
+    pub fn strip_prefix(&self, prefix: &Nibbles) -> Option<Nibbles> {
+        self.0
+            .strip_prefix(prefix.0.as_slice())
+            .map(|rest| Self(rest.to_vec()))
+    }
 }
 
 impl fmt::Display for Nibbles {
@@ -149,8 +178,8 @@ impl fmt::Display for Nibbles {
 
 #[cfg(test)]
 mod tests {
-    use super::{Bytes, Nibbles};
-    use ethers::types::U256;
+    use super::{account_key, storage_key, Bytes, Error, Nibbles};
+    use ethers::types::{Address, U256};
     use ethers_core::utils::hex;
 
     #[test]
@@ -273,6 +302,19 @@ mod tests {
         assert!(Nibbles::from_encoded_path(vec![0x52, 0x34, 0x56].into()).is_err());
     }
 
+    #[test]
+    pub fn test_decode_bad_path_2_empty() {
+        assert!(Nibbles::from_encoded_path(vec![].into()).is_err());
+    }
+
+    #[test]
+    pub fn test_from_u4_vec_invalid_nibble() {
+        assert!(matches!(
+            Nibbles::from_u4_vec(vec![0x1, 0xf, 0x10]),
+            Err(Error::InvalidNibble(0x10))
+        ));
+    }
+
     #[test]
     pub fn test_slice_1() {
         let nibbles = Nibbles::from_raw_path("123456".parse().unwrap());
@@ -307,6 +349,56 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_ord_1() {
+        let nibbles = |u4_vec: Vec<u8>| Nibbles::from_u4_vec(u4_vec).unwrap();
+
+        // the raw path of [0x1] is 0x01 and the one of [0x0, 0x2] is 0x02
+        assert!(nibbles(vec![0x1]).to_raw_path() < nibbles(vec![0x0, 0x2]).to_raw_path());
+        assert!(nibbles(vec![0x0, 0x2]) < nibbles(vec![0x1]));
+
+        // 0x0a against 0x12
+        assert!(nibbles(vec![0xa]).to_raw_path() < nibbles(vec![0x1, 0x2]).to_raw_path());
+        assert!(nibbles(vec![0x1, 0x2]) < nibbles(vec![0xa]));
+
+        // a prefix sorts first
+        let mut paths = [
+            nibbles(vec![0x1, 0x2, 0x0]),
+            nibbles(vec![0x1, 0x3]),
+            nibbles(vec![0x1, 0x2]),
+            nibbles(vec![0x1]),
+        ];
+        paths.sort();
+        assert_eq!(
+            paths.iter().map(Nibbles::to_u4_vec).collect::<Vec<_>>(),
+            vec![
+                vec![0x1],
+                vec![0x1, 0x2],
+                vec![0x1, 0x2, 0x0],
+                vec![0x1, 0x3]
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_prefix_1() {
+        let nibbles = Nibbles::from_raw_path("123456".parse().unwrap());
+        let prefix = Nibbles::from_u4_vec(vec![0x1, 0x2, 0x3]).unwrap();
+        assert!(nibbles.starts_with(&prefix));
+        assert!(nibbles.starts_with(&nibbles));
+        assert_eq!(
+            nibbles.strip_prefix(&prefix).unwrap().to_u4_vec(),
+            vec![0x4, 0x5, 0x6]
+        );
+        assert_eq!(nibbles.strip_prefix(&nibbles).unwrap().len(), 0);
+
+        // 0x12 is a byte prefix of 0x123456 but the odd path [0x2] is not
+        let odd = Nibbles::from_u4_vec(vec![0x2]).unwrap();
+        assert!(!nibbles.starts_with(&odd));
+        assert!(nibbles.strip_prefix(&odd).is_none());
+        assert!(!prefix.starts_with(&nibbles));
+    }
+
     #[test]
     pub fn test_from_address_1() {
         let nibbles = Nibbles::from_address(
@@ -352,4 +444,51 @@ mod tests {
             "405787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace"
         );
     }
+
+    #[test]
+    pub fn test_from_index_1() {
+        for (index, key) in [
+            (0, "80"),
+            (1, "01"),
+            (127, "7f"),
+            (128, "8180"),
+            (299, "82012b"),
+        ] {
+            assert_eq!(hex::encode(Nibbles::from_index(index).to_raw_path()), key);
+        }
+        assert_eq!(Nibbles::from_index(u64::MAX).len(), 18);
+    }
+
+    #[test]
+    pub fn test_account_key_known_answers() {
+        assert_eq!(
+            hex::encode(account_key(Address::zero())),
+            "5380c7b7ae81a58eb98d9c78de4a1fd7fd9535fc953ed2be602daaa41767312a"
+        );
+        // WETH on mainnet
+        assert_eq!(
+            hex::encode(account_key(
+                "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+                    .parse()
+                    .unwrap()
+            )),
+            "8679e8eda65bd257638cf8cf09b8238888947cc3c0bea2aa2cc3f1c4ac7a3002"
+        );
+    }
+
+    #[test]
+    pub fn test_storage_key_known_answers() {
+        assert_eq!(
+            hex::encode(storage_key(U256::zero())),
+            "290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563"
+        );
+        assert_eq!(
+            hex::encode(storage_key(U256::from(1))),
+            "b10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf6"
+        );
+        assert_eq!(
+            Nibbles::from_uint(U256::from(1)).unwrap().to_raw_path(),
+            Bytes::from(storage_key(U256::from(1)).as_bytes().to_vec())
+        );
+    }
 }
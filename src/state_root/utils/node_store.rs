@@ -0,0 +1,277 @@
+use std::{fmt, sync::Arc};
+
+use ethers::types::H256;
+
+use super::trie::NodeData;
+
+/// Loaded nodes of a trie keyed by their hash, held in a persistent 16-ary trie on
+/// the nibbles of the hash. Clones share every level, and a change copies only
+/// the levels on the path to its hash, so a write after a clone costs as many
+/// levels as the store is deep rather than a copy of all the nodes.
+#[derive(Clone, Default)]
+pub struct NodeStore {
+    root: Arc<Level>,
+    len: usize,
+}
+
+// a level only exists where two hashes share the nibbles above it, so the shape
+// depends on the hashes held and not on the order they came in
+#[derive(Clone, Default, PartialEq)]
+struct Level([Option<Slot>; 16]);
+
+#[derive(Clone)]
+enum Slot {
+    Node(Arc<(H256, NodeData)>),
+    Level(Arc<Level>),
+}
+
+impl PartialEq for Slot {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Slot::Node(a), Slot::Node(b)) => Arc::ptr_eq(a, b) || a == b,
+            (Slot::Level(a), Slot::Level(b)) => Arc::ptr_eq(a, b) || a == b,
+            _ => false,
+        }
+    }
+}
+
+// nibble of `hash` at `depth`, high nibble first
+fn nibble(hash: &H256, depth: usize) -> usize {
+    let byte = hash.as_bytes()[depth / 2];
+    if depth % 2 == 0 {
+        (byte >> 4) as usize
+    } else {
+        (byte & 0xf) as usize
+    }
+}
+
+impl NodeStore {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, hash: &H256) -> Option<&NodeData> {
+        let mut level = &self.root;
+        let mut depth = 0;
+        loop {
+            match &level.0[nibble(hash, depth)] {
+                Some(Slot::Node(entry)) if entry.0 == *hash => return Some(&entry.1),
+                Some(Slot::Level(next)) => level = next,
+                _ => return None,
+            }
+            depth += 1;
+        }
+    }
+
+    pub fn contains_key(&self, hash: &H256) -> bool {
+        self.get(hash).is_some()
+    }
+
+    /// Inserts or replaces the node at `hash`, returning whether it is new.
+    pub fn insert(&mut self, hash: H256, node: NodeData) -> bool {
+        let inserted = insert_at(Arc::make_mut(&mut self.root), 0, hash, node);
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    pub fn remove(&mut self, hash: &H256) -> Option<NodeData> {
+        // checked first, so that removing a node that is not there copies nothing
+        if !self.contains_key(hash) {
+            return None;
+        }
+        let removed = remove_at(Arc::make_mut(&mut self.root), 0, hash);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Drops every node, keeping the memory of the top level unless a clone still
+    /// shares it.
+    pub fn clear(&mut self) {
+        match Arc::get_mut(&mut self.root) {
+            Some(level) => *level = Level::default(),
+            None => self.root = Arc::default(),
+        }
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&H256, &NodeData)> {
+        let mut stack = vec![self.root.0.iter()];
+        std::iter::from_fn(move || loop {
+            match stack.last_mut()?.next() {
+                Some(Some(Slot::Node(entry))) => return Some((&entry.0, &entry.1)),
+                Some(Some(Slot::Level(level))) => stack.push(level.0.iter()),
+                Some(None) => {}
+                None => {
+                    stack.pop();
+                }
+            }
+        })
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &NodeData> {
+        self.iter().map(|(_, node)| node)
+    }
+
+    /// Whether both stores are the same that a clone was made of, with no change
+    /// since to either of them.
+    pub fn ptr_eq(&self, other: &NodeStore) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+}
+
+fn insert_at(level: &mut Level, depth: usize, hash: H256, node: NodeData) -> bool {
+    let slot = &mut level.0[nibble(&hash, depth)];
+    match slot {
+        None => {
+            *slot = Some(Slot::Node(Arc::new((hash, node))));
+            true
+        }
+        Some(Slot::Node(entry)) if entry.0 == hash => {
+            *slot = Some(Slot::Node(Arc::new((hash, node))));
+            false
+        }
+        Some(Slot::Node(entry)) => {
+            // two hashes share the nibbles so far, they go one level down
+            let mut next = Level::default();
+            next.0[nibble(&entry.0, depth + 1)] = Some(Slot::Node(Arc::clone(entry)));
+            insert_at(&mut next, depth + 1, hash, node);
+            *slot = Some(Slot::Level(Arc::new(next)));
+            true
+        }
+        Some(Slot::Level(next)) => insert_at(Arc::make_mut(next), depth + 1, hash, node),
+    }
+}
+
+fn remove_at(level: &mut Level, depth: usize, hash: &H256) -> Option<NodeData> {
+    let index = nibble(hash, depth);
+    if matches!(&level.0[index], Some(Slot::Node(entry)) if entry.0 == *hash) {
+        return match level.0[index].take() {
+            Some(Slot::Node(entry)) => {
+                Some(Arc::try_unwrap(entry).map_or_else(|entry| entry.1.clone(), |(_, node)| node))
+            }
+            _ => None,
+        };
+    }
+    let next = match &mut level.0[index] {
+        Some(Slot::Level(next)) => Arc::make_mut(next),
+        _ => return None,
+    };
+    let removed = remove_at(next, depth + 1, hash)?;
+
+    // a level left with a single node folds back into this one
+    let mut slots = next.0.iter().flatten();
+    let folded = match (slots.next(), slots.next()) {
+        (None, _) => Some(None),
+        (Some(Slot::Node(entry)), None) => Some(Some(Slot::Node(Arc::clone(entry)))),
+        _ => None,
+    };
+    if let Some(slot) = folded {
+        level.0[index] = slot;
+    }
+    Some(removed)
+}
+
+impl PartialEq for NodeStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && (self.ptr_eq(other) || self.root == other.root)
+    }
+}
+
+impl fmt::Debug for NodeStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::{types::H256, utils::keccak256};
+
+    use super::{NodeStore, Slot};
+    use crate::state_root::utils::{Nibbles, NodeData};
+
+    fn entry(i: u64) -> (H256, NodeData) {
+        let node = NodeData::Leaf {
+            key: Nibbles::from_raw_path_str("0x12"),
+            value: vec![(i % 255) as u8 + 1; 32].into(),
+        };
+        (H256::from(keccak256(i.to_be_bytes())), node)
+    }
+
+    #[test]
+    pub fn test_node_store_1() {
+        let mut store = NodeStore::default();
+        for i in 0..500 {
+            let (hash, node) = entry(i);
+            assert!(store.insert(hash, node));
+        }
+        let (hash, node) = entry(7);
+        assert!(!store.insert(hash, node.clone()));
+        assert_eq!(store.len(), 500);
+        assert_eq!(store.get(&hash), Some(&node));
+        assert_eq!(store.iter().count(), 500);
+        assert!(store.get(&H256::zero()).is_none());
+
+        // the shape does not depend on the order of the inserts
+        let mut reversed = NodeStore::default();
+        for i in (0..500).rev() {
+            let (hash, node) = entry(i);
+            reversed.insert(hash, node);
+        }
+        assert_eq!(store, reversed);
+
+        for i in 0..500 {
+            let (hash, node) = entry(i);
+            assert_eq!(store.remove(&hash), Some(node));
+            assert!(store.remove(&hash).is_none());
+            if i == 249 {
+                let mut half = NodeStore::default();
+                for i in 250..500 {
+                    let (hash, node) = entry(i);
+                    half.insert(hash, node);
+                }
+                assert_eq!(store, half);
+            }
+        }
+        assert_eq!(store.len(), 0);
+        assert_eq!(store, NodeStore::default());
+    }
+
+    #[test]
+    pub fn test_node_store_2_clone_shares_levels() {
+        let mut store = NodeStore::default();
+        for i in 0..1000 {
+            let (hash, node) = entry(i);
+            store.insert(hash, node);
+        }
+        let mut clone = store.clone();
+        assert!(clone.ptr_eq(&store));
+
+        let (hash, node) = entry(1000);
+        clone.insert(hash, node);
+        assert!(!clone.ptr_eq(&store));
+        assert_eq!(store.len(), 1000);
+        assert!(store.get(&hash).is_none());
+
+        // only the level on the path to the new hash is copied, the 15 others of the
+        // top level are still shared
+        let index = super::nibble(&hash, 0);
+        for (i, (a, b)) in store.root.0.iter().zip(clone.root.0.iter()).enumerate() {
+            match (a, b) {
+                (Some(Slot::Level(a)), Some(Slot::Level(b))) => {
+                    assert_eq!(Arc::ptr_eq(a, b), i != index)
+                }
+                _ => panic!("1000 hashes fill every slot of the top level"),
+            }
+        }
+
+        clone.remove(&hash);
+        assert_eq!(clone, store);
+    }
+}
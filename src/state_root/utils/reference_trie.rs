@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use eth_trie::{EthTrie, MemoryDB, Trie as _};
+use ethers::types::{Bytes, H256};
+
+use crate::error::Error;
+
+/// Wrapper around `eth_trie` used as the reference implementation when
+/// differential testing our `Trie`.
+pub struct ReferenceTrie(EthTrie<MemoryDB>);
+
+impl ReferenceTrie {
+    pub fn new() -> Self {
+        ReferenceTrie(EthTrie::new(Arc::new(MemoryDB::new(true))))
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.0
+            .insert(key, value)
+            .map_err(|err| Error::ReferenceTrieError(format!("insert failed: {:?}", err)))
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.0
+            .remove(key)
+            .map(|_| ())
+            .map_err(|err| Error::ReferenceTrieError(format!("remove failed: {:?}", err)))
+    }
+
+    pub fn root(&mut self) -> Result<H256, Error> {
+        let root = self
+            .0
+            .root_hash()
+            .map_err(|err| Error::ReferenceTrieError(format!("root failed: {:?}", err)))?;
+        Ok(H256::from_slice(root.as_bytes()))
+    }
+
+    pub fn get_proof(&mut self, key: &[u8]) -> Result<Vec<Bytes>, Error> {
+        let proof = self
+            .0
+            .get_proof(key)
+            .map_err(|err| Error::ReferenceTrieError(format!("proof failed: {:?}", err)))?;
+        Ok(proof.into_iter().map(Bytes::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ethers::{
+        types::{Bytes, H256},
+        utils::keccak256,
+    };
+    use proptest::{collection::vec, prelude::*};
+
+    use super::ReferenceTrie;
+    use crate::state_root::utils::{Nibbles, Trie};
+
+    // the initial entries are proven out of the reference trie, and then only updates
+    // and deletes are applied to both
+    #[derive(Clone, Debug)]
+    enum Op {
+        Update(usize, Vec<u8>),
+        Delete(usize),
+    }
+
+    fn op_strategy() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (any::<usize>(), vec(any::<u8>(), 1..32)).prop_map(|(i, v)| Op::Update(i, v)),
+            any::<usize>().prop_map(Op::Delete),
+        ]
+    }
+
+    fn assert_proofs_round_trip(reference: &mut ReferenceTrie, live: &BTreeMap<H256, Vec<u8>>) {
+        let root = reference.root().unwrap();
+        for (key, value) in live.iter() {
+            let mut trie = Trie::from_root(root);
+            trie.load_proof(
+                Nibbles::from_raw_path(Bytes::from(key.as_bytes().to_vec())),
+                Bytes::from(value.clone()),
+                reference.get_proof(key.as_bytes()).unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_differential_against_reference(
+            entries in vec((any::<u64>(), vec(any::<u8>(), 1..32)), 1..32),
+            ops in vec(op_strategy(), 0..32),
+        ) {
+            // secure trie style keys, so nodes are never short enough to be inlined
+            let entries = entries
+                .into_iter()
+                .map(|(seed, value)| (H256::from(keccak256(seed.to_be_bytes())), value))
+                .collect::<BTreeMap<_, _>>();
+            let keys = entries.keys().cloned().collect::<Vec<_>>();
+
+            let mut reference = ReferenceTrie::new();
+            for (key, value) in entries.iter() {
+                reference.insert(key.as_bytes(), value).unwrap();
+            }
+
+            // building from nothing must match the reference on the same entries
+            let mut built = Trie::empty();
+            for (key, value) in entries.iter() {
+                built
+                    .set_value(
+                        Nibbles::from_raw_path(Bytes::from(key.as_bytes().to_vec())),
+                        Bytes::from(value.clone()),
+                    )
+                    .unwrap();
+            }
+            prop_assert_eq!(built.root.unwrap(), reference.root().unwrap());
+
+            let mut trie = Trie::from_root(reference.root().unwrap());
+            for (key, value) in entries.iter() {
+                trie.load_proof(
+                    Nibbles::from_raw_path(Bytes::from(key.as_bytes().to_vec())),
+                    Bytes::from(value.clone()),
+                    reference.get_proof(key.as_bytes()).unwrap(),
+                )
+                .unwrap();
+            }
+
+            let mut live = entries;
+            for op in ops {
+                match op {
+                    Op::Update(i, value) => {
+                        if live.is_empty() {
+                            continue;
+                        }
+                        let key = *live.keys().nth(i % live.len()).unwrap();
+                        reference.insert(key.as_bytes(), &value).unwrap();
+                        trie.set_value(
+                            Nibbles::from_raw_path(Bytes::from(key.as_bytes().to_vec())),
+                            Bytes::from(value.clone()),
+                        )
+                        .unwrap();
+                        live.insert(key, value);
+                    }
+                    Op::Delete(i) => {
+                        // may pick an already deleted key, which is a no-op for both
+                        let key = keys[i % keys.len()];
+                        reference.remove(key.as_bytes()).unwrap();
+                        trie.remove_value(Nibbles::from_raw_path(Bytes::from(
+                            key.as_bytes().to_vec(),
+                        )))
+                        .unwrap();
+                        live.remove(&key);
+                    }
+                }
+
+                prop_assert_eq!(trie.root.unwrap(), reference.root().unwrap());
+                assert_proofs_round_trip(&mut reference, &live);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+
+#[cfg(feature = "alloy-rlp")]
+use ethers::utils::rlp::DecoderError;
+use ethers::{types::Bytes, utils::rlp::Rlp};
+
+use crate::error::{DecodeContext, Error};
+
+/// Item of the RLP list a trie node is encoded as.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RlpNodeItem {
+    Data(Bytes),
+    // a nested list, as an inlined node would be
+    List,
+}
+
+/// RLP backend used to split trie nodes into their items, so that users of
+/// another RLP stack do not need to pull in the one of ethers.
+pub trait RlpNodeDecoder: fmt::Debug {
+    /// Returns the items of the list at the start of `raw`, bytes after the list
+    /// are ignored.
+    fn decode_items(&self, raw: &[u8]) -> Result<Vec<RlpNodeItem>, Error>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DefaultRlpDecoder;
+
+impl RlpNodeDecoder for DefaultRlpDecoder {
+    fn decode_items(&self, raw: &[u8]) -> Result<Vec<RlpNodeItem>, Error> {
+        let rlp = Rlp::new(raw);
+        (0..rlp.item_count().decoding("trie node")?)
+            .map(|i| {
+                let item = rlp.at(i).decoding("trie node")?;
+                Ok(if item.is_list() {
+                    RlpNodeItem::List
+                } else {
+                    RlpNodeItem::Data(Bytes::from(item.data().decoding("trie node")?.to_owned()))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Decoder on `alloy-rlp`. Unlike the default one it rejects single bytes behind
+/// a string prefix, so such nodes do not decode even when not asking for the
+/// canonical encoding.
+#[cfg(feature = "alloy-rlp")]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AlloyRlpDecoder;
+
+#[cfg(feature = "alloy-rlp")]
+impl RlpNodeDecoder for AlloyRlpDecoder {
+    fn decode_items(&self, raw: &[u8]) -> Result<Vec<RlpNodeItem>, Error> {
+        let mut buf = raw;
+        let header = alloy_rlp::Header::decode(&mut buf).map_err(alloy_error)?;
+        if !header.list {
+            return Err(Error::RlpDecoderError(
+                "trie node",
+                DecoderError::RlpExpectedToBeList,
+            ));
+        }
+        if buf.len() < header.payload_length {
+            return Err(Error::RlpDecoderError(
+                "trie node",
+                DecoderError::RlpIsTooShort,
+            ));
+        }
+
+        let mut payload = &buf[..header.payload_length];
+        let mut items = Vec::new();
+        while !payload.is_empty() {
+            let item = alloy_rlp::Header::decode(&mut payload).map_err(alloy_error)?;
+            if payload.len() < item.payload_length {
+                return Err(Error::RlpDecoderError(
+                    "trie node",
+                    DecoderError::RlpIsTooShort,
+                ));
+            }
+            let (data, rest) = payload.split_at(item.payload_length);
+            items.push(if item.list {
+                RlpNodeItem::List
+            } else {
+                RlpNodeItem::Data(Bytes::from(data.to_vec()))
+            });
+            payload = rest;
+        }
+        Ok(items)
+    }
+}
+
+// errors are reported the same way whichever decoder is used
+#[cfg(feature = "alloy-rlp")]
+fn alloy_error(err: alloy_rlp::Error) -> Error {
+    let err = match err {
+        alloy_rlp::Error::InputTooShort => DecoderError::RlpIsTooShort,
+        alloy_rlp::Error::UnexpectedString => DecoderError::RlpExpectedToBeList,
+        alloy_rlp::Error::UnexpectedList => DecoderError::RlpExpectedToBeData,
+        alloy_rlp::Error::Custom(message) => DecoderError::Custom(message),
+        _ => DecoderError::Custom("invalid rlp"),
+    };
+    Error::RlpDecoderError("trie node", err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultRlpDecoder, RlpNodeDecoder, RlpNodeItem};
+
+    #[test]
+    pub fn test_default_rlp_decoder_1() {
+        // leaf holding 0x08
+        let raw =
+            hex_bytes("0xe3a120290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e56308");
+        assert_eq!(
+            DefaultRlpDecoder.decode_items(&raw).unwrap(),
+            vec![
+                RlpNodeItem::Data(hex_bytes(
+                    "0x20290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563"
+                )),
+                RlpNodeItem::Data(hex_bytes("0x08")),
+            ]
+        );
+
+        // a list in place of the value
+        let raw = hex_bytes("0xc482aabbc0");
+        assert_eq!(
+            DefaultRlpDecoder.decode_items(&raw).unwrap(),
+            vec![RlpNodeItem::Data(hex_bytes("0xaabb")), RlpNodeItem::List]
+        );
+
+        // not a list
+        assert!(DefaultRlpDecoder
+            .decode_items(&hex_bytes("0x8208"))
+            .is_err());
+    }
+
+    #[cfg(feature = "alloy-rlp")]
+    #[test]
+    pub fn test_alloy_rlp_decoder_1_same_nodes() {
+        use super::AlloyRlpDecoder;
+        use crate::state_root::utils::{NodeData, NodeEncoding};
+
+        for raw in [
+            // branch of test_trie_new_two_element_1 and its leaf
+            "0xf851a0e97150c3ed221a6f46bdcd44e8a2d44825bc781fa48f797e9df2f8ceff52a43e8080808080808080808080a09487c8e7f28469b9f72cd6be094b555c3882c0653f11b208ff76bf8caee5043280808080",
+            "0xe2a0336b6384b5eca791c62761152d0c79bb0604c104a5fb6f4eb0703f3154bb3db009",
+            // extension of test_trie_new_three_element_1
+            "0xe583165a7ba0e46db0426b9d34c7b2df7baf0480777946e6b5b74a0572592b0229a4edaed944",
+            // branch with a value and no children
+            "0xf180808080808080808080808080808080a0abababababababababababababababababababababababababababababababab",
+            // account leaf of the tx sender of test_state_1
+            "0xf889a03e19976962fea3751225213669050369b7cd26650bc43815007705e945b5aa57b866f86403a0ffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a0c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+            // leaf followed by a trailing byte
+            "0xe3a120290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e5630800",
+        ] {
+            let raw = hex_bytes(raw);
+            assert_eq!(
+                AlloyRlpDecoder.decode_items(&raw).unwrap(),
+                DefaultRlpDecoder.decode_items(&raw).unwrap()
+            );
+            assert_eq!(
+                NodeData::new_with(raw.clone(), NodeEncoding::Lenient, &AlloyRlpDecoder).unwrap(),
+                NodeData::new(raw, NodeEncoding::Lenient).unwrap()
+            );
+        }
+
+        // 0x08 behind a string prefix
+        let raw = hex_bytes(
+            "0xe4a120290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e5638108",
+        );
+        assert!(NodeData::new(raw.clone(), NodeEncoding::Lenient).is_ok());
+        assert!(NodeData::new_with(raw, NodeEncoding::Lenient, &AlloyRlpDecoder).is_err());
+    }
+
+    fn hex_bytes(hex: &str) -> ethers::types::Bytes {
+        hex.parse().unwrap()
+    }
+}
@@ -0,0 +1,232 @@
+use super::utils::{Nibbles, Trie, TrieKind};
+use crate::error::Error;
+use ethers::{
+    prelude::EthDisplay,
+    types::{Address, Bytes, H256},
+    utils::rlp::RlpStream,
+};
+
+/// Trie of the withdrawals of a block, whose root is the `withdrawalsRoot` of the
+/// header since Shanghai. Unlike the state tries its keys are not hashed, a
+/// withdrawal is keyed by the RLP of its position in the block and holds the RLP
+/// of `[index, validator_index, address, amount]`.
+#[derive(Debug, Clone, EthDisplay, PartialEq)]
+pub struct WithdrawalsTrie(Trie);
+
+/// Withdrawal of a block (EIP-4895), `amount` being in gwei.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: Address,
+    pub amount: u64,
+}
+
+impl Withdrawal {
+    pub fn rlp(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(4);
+        stream
+            .append(&self.index)
+            .append(&self.validator_index)
+            .append(&self.address)
+            .append(&self.amount);
+        Bytes::from(stream.out().to_vec())
+    }
+}
+
+impl Default for WithdrawalsTrie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WithdrawalsTrie {
+    pub fn new() -> Self {
+        WithdrawalsTrie(indexed(Trie::new()))
+    }
+
+    pub fn from_root(root: H256) -> Self {
+        WithdrawalsTrie(indexed(Trie::from_root(root)))
+    }
+
+    /// Builds the trie of a whole block, `withdrawals` being in the order of the
+    /// block. An empty block gives the empty root.
+    pub fn from_withdrawals(withdrawals: &[Withdrawal]) -> Result<Self, Error> {
+        let trie = Trie::from_entries(
+            withdrawals
+                .iter()
+                .enumerate()
+                .map(|(index, withdrawal)| (Nibbles::from_index(index as u64), withdrawal.rlp()))
+                .collect(),
+        )?;
+        Ok(WithdrawalsTrie(indexed(trie)))
+    }
+
+    pub fn root(&self) -> Option<H256> {
+        self.0.root
+    }
+
+    // raw RLP of the withdrawal, empty if the block has none at `index`
+    pub fn get_withdrawal(&self, index: u64) -> Result<Bytes, Error> {
+        self.0.get_value(Nibbles::from_index(index))
+    }
+
+    /// `withdrawal_rlp` is empty for a proof that the block has no withdrawal at
+    /// `index`.
+    pub fn load_proof(
+        &mut self,
+        index: u64,
+        withdrawal_rlp: Bytes,
+        proof: Vec<Bytes>,
+    ) -> Result<(), Error> {
+        self.0
+            .load_proof(Nibbles::from_index(index), withdrawal_rlp, proof)
+    }
+
+    /// Proof of the withdrawal at `index`, or of there being none at `index`.
+    pub fn prove(&self, index: u64) -> Result<Vec<Bytes>, Error> {
+        self.0.prove(Nibbles::from_index(index))
+    }
+}
+
+/// `withdrawalsRoot` of a block with `withdrawals`.
+pub fn withdrawals_root(withdrawals: &[Withdrawal]) -> Result<H256, Error> {
+    WithdrawalsTrie::from_withdrawals(withdrawals)?
+        .root()
+        .ok_or(Error::RootNotSet)
+}
+
+/// Checks that the withdrawal at `index` of the block with `withdrawals_root` is
+/// `withdrawal_rlp`.
+pub fn verify_withdrawal(
+    withdrawals_root: H256,
+    index: u64,
+    withdrawal_rlp: Bytes,
+    proof: Vec<Bytes>,
+) -> Result<(), Error> {
+    WithdrawalsTrie::from_root(withdrawals_root).load_proof(index, withdrawal_rlp, proof)
+}
+
+// an index past the last one of the block is what the trie has no value for
+pub(super) fn indexed(mut trie: Trie) -> Trie {
+    trie.set_kind(TrieKind::Indexed);
+    trie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_withdrawal, withdrawals_root, Bytes, Withdrawal, WithdrawalsTrie, H256};
+    use crate::state_root::utils::empty_root;
+
+    // three withdrawals in the layout of a post-Shanghai block, index 0 (key 0x80)
+    // sits below nibble 8 of the root branch and indexes 1 and 2 share the branch
+    // below nibble 0
+    const WITHDRAWALS_ROOT: &str =
+        "0x058a2b5d12ba095ee2cb474e26ff940ebfd7a7cca9260a18bd5ff9d03e794158";
+    const ROOT_NODE: &str = "0xf851a084239138ec32eca4f23b1020de8e4fd9012b008a2ede99756a25c8d118d4ecfa80808080808080a05a3619ca41b3b1c910d0e5dcdeef56dbfe12f0ce6123a7c41a653c6a108f1be78080808080808080";
+    const BRANCH_NODE: &str = "0xf85180a0ef4a60119858be3df19763c3fe843364301d7866ab81e76516619f30ce891111a0f7e19a677b283a0b1736c118411764f33a52e7bbf496087f740dd6cf8581827a8080808080808080808080808080";
+
+    fn parse_proof(entries: &[&str]) -> Vec<Bytes> {
+        entries.iter().map(|entry| entry.parse().unwrap()).collect()
+    }
+
+    #[test]
+    pub fn test_withdrawals_trie_verify_1() {
+        let root: H256 = WITHDRAWALS_ROOT.parse().unwrap();
+
+        // index 0, validator 154321, 3242107 gwei
+        let withdrawal: Bytes = "0xde8083025ad194b9d7934878b5fb9610b3fe8a5e441e8fad7e293f8331787b"
+            .parse()
+            .unwrap();
+        let proof = parse_proof(&[
+            ROOT_NODE,
+            "0xe1309fde8083025ad194b9d7934878b5fb9610b3fe8a5e441e8fad7e293f8331787b",
+        ]);
+        verify_withdrawal(root, 0, withdrawal.clone(), proof.clone()).unwrap();
+
+        // the same withdrawal claimed at another index
+        assert!(verify_withdrawal(root, 8, withdrawal, proof).is_err());
+
+        // index 2, validator 154323, 32003210218 gwei
+        let withdrawal: Bytes =
+            "0xe00283025ad3948ab0cd7a0b1b2e9e3ea3e7b4c4ab20e1bf3a67128507738a3bea"
+                .parse()
+                .unwrap();
+        let mut trie = WithdrawalsTrie::from_root(root);
+        trie.load_proof(
+            2,
+            withdrawal.clone(),
+            parse_proof(&[
+                ROOT_NODE,
+                BRANCH_NODE,
+                "0xe320a1e00283025ad3948ab0cd7a0b1b2e9e3ea3e7b4c4ab20e1bf3a67128507738a3bea",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(trie.get_withdrawal(2).unwrap(), withdrawal);
+    }
+
+    #[test]
+    pub fn test_withdrawals_trie_verify_2_absent() {
+        let root: H256 = WITHDRAWALS_ROOT.parse().unwrap();
+
+        // the branch of indexes 1 and 2 has nothing at nibble 3
+        let proof = parse_proof(&[ROOT_NODE, BRANCH_NODE]);
+        verify_withdrawal(root, 3, Bytes::new(), proof.clone()).unwrap();
+        // unlike a storage slot, an absent index does not hold 0x00
+        assert!(verify_withdrawal(root, 3, "0x00".parse().unwrap(), proof.clone()).is_err());
+        assert!(verify_withdrawal(root, 3, "0xe00383025ad4".parse().unwrap(), proof).is_err());
+    }
+
+    #[test]
+    pub fn test_withdrawals_root_1() {
+        let withdrawals = [
+            (
+                0,
+                154321,
+                "0xb9d7934878b5fb9610b3fe8a5e441e8fad7e293f",
+                3242107,
+            ),
+            (
+                1,
+                154322,
+                "0xb9d7934878b5fb9610b3fe8a5e441e8fad7e293f",
+                3289096,
+            ),
+            (
+                2,
+                154323,
+                "0x8ab0cd7a0b1b2e9e3ea3e7b4c4ab20e1bf3a6712",
+                32003210218,
+            ),
+        ]
+        .into_iter()
+        .map(|(index, validator_index, address, amount)| Withdrawal {
+            index,
+            validator_index,
+            address: address.parse().unwrap(),
+            amount,
+        })
+        .collect::<Vec<_>>();
+        let root: H256 = WITHDRAWALS_ROOT.parse().unwrap();
+        assert_eq!(withdrawals_root(&withdrawals).unwrap(), root);
+        assert_eq!(withdrawals_root(&[]).unwrap(), empty_root());
+
+        let trie = WithdrawalsTrie::from_withdrawals(&withdrawals).unwrap();
+        let proof = trie.prove(2).unwrap();
+        assert_eq!(
+            proof,
+            parse_proof(&[
+                ROOT_NODE,
+                BRANCH_NODE,
+                "0xe320a1e00283025ad3948ab0cd7a0b1b2e9e3ea3e7b4c4ab20e1bf3a67128507738a3bea",
+            ])
+        );
+        verify_withdrawal(root, 2, withdrawals[2].rlp(), proof).unwrap();
+
+        // past the last withdrawal
+        let proof = trie.prove(3).unwrap();
+        assert_eq!(proof, parse_proof(&[ROOT_NODE, BRANCH_NODE]));
+        verify_withdrawal(root, 3, Bytes::new(), proof).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::BTreeSet;
+
+use ethers::types::{Address, H256, U256};
+
+use super::state_trie::StateTrie;
+use crate::error::Error;
+
+// Plain mirrors of the zkevm-circuits inputs, so that the conversion does not pull
+// in a dependency on the circuit crates.
+
+/// A loaded account as the circuit's state db sees it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateEntry {
+    pub address: Address,
+    pub nonce: U256,
+    pub balance: U256,
+    pub code_hash: H256,
+    // (slot, value) of the loaded slots, in slot order
+    pub storage: Vec<(U256, U256)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccountField {
+    Nonce,
+    Balance,
+    CodeHash,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MptKey {
+    Account {
+        address: Address,
+        field: AccountField,
+    },
+    Storage {
+        address: Address,
+        slot: U256,
+    },
+}
+
+/// A row of the MPT table, values are words as in the circuit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MptUpdate {
+    pub key: MptKey,
+    pub old_value: U256,
+    pub new_value: U256,
+}
+
+impl StateTrie {
+    /// Every loaded account with its loaded storage slots, in address order.
+    pub fn to_state_entries(&self) -> Result<Vec<StateEntry>, Error> {
+        self.loaded_keys()
+            .map(|(address, slots)| self.state_entry(*address, slots))
+            .collect()
+    }
+
+    /// Pairs the values of this (pre) state with the `post` state for every key
+    /// loaded on either side, keeping only the keys whose value changed. An account
+    /// missing on one side, i.e. created or deleted, reads as all zeros there.
+    pub fn to_mpt_updates(&self, post: &StateTrie) -> Result<Vec<MptUpdate>, Error> {
+        let addresses = self
+            .loaded_keys()
+            .chain(post.loaded_keys())
+            .map(|(address, _)| *address)
+            .collect::<BTreeSet<_>>();
+
+        let mut updates = Vec::new();
+        for address in addresses {
+            let pre = self.state_entry_or_default(address, post)?;
+            let post = post.state_entry_or_default(address, self)?;
+
+            for (field, old_value, new_value) in [
+                (AccountField::Nonce, pre.nonce, post.nonce),
+                (AccountField::Balance, pre.balance, post.balance),
+                (
+                    AccountField::CodeHash,
+                    U256::from_big_endian(pre.code_hash.as_bytes()),
+                    U256::from_big_endian(post.code_hash.as_bytes()),
+                ),
+            ] {
+                if old_value != new_value {
+                    updates.push(MptUpdate {
+                        key: MptKey::Account { address, field },
+                        old_value,
+                        new_value,
+                    });
+                }
+            }
+
+            // both sides hold the same slots, see state_entry_or_default
+            for ((slot, old_value), (_, new_value)) in pre.storage.into_iter().zip(post.storage) {
+                if old_value != new_value {
+                    updates.push(MptUpdate {
+                        key: MptKey::Storage { address, slot },
+                        old_value,
+                        new_value,
+                    });
+                }
+            }
+        }
+        Ok(updates)
+    }
+
+    fn state_entry(&self, address: Address, slots: &BTreeSet<U256>) -> Result<StateEntry, Error> {
+        let account_data = self.account_trie.get_account_data(address)?;
+        let storage_trie = self.get_storage_trie(account_data.storage_root);
+        Ok(StateEntry {
+            address,
+            nonce: account_data.nonce,
+            balance: account_data.balance,
+            code_hash: account_data.code_hash,
+            storage: slots
+                .iter()
+                .map(|slot| Ok((*slot, storage_trie.get_value(*slot)?)))
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+
+    // entry over the slots loaded on either this or the other state
+    fn state_entry_or_default(
+        &self,
+        address: Address,
+        other: &StateTrie,
+    ) -> Result<StateEntry, Error> {
+        let slots = self
+            .loaded_keys()
+            .chain(other.loaded_keys())
+            .filter(|(loaded, _)| **loaded == address)
+            .flat_map(|(_, slots)| slots.iter().cloned())
+            .collect::<BTreeSet<_>>();
+
+        if self.loaded_keys().any(|(loaded, _)| *loaded == address) {
+            self.state_entry(address, &slots)
+        } else {
+            Ok(StateEntry {
+                address,
+                nonce: U256::zero(),
+                balance: U256::zero(),
+                code_hash: H256::zero(),
+                storage: slots.into_iter().map(|slot| (slot, U256::zero())).collect(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccountField, MptKey, MptUpdate};
+    use crate::state_root::state_trie::StateTrie;
+    use ethers::types::{Address, EIP1186ProofResponse, StorageProof, U256};
+
+    fn load_pre_state() -> StateTrie {
+        // a contract was deployed on geth --dev, see state_trie tests
+        let mut trie = StateTrie::new();
+
+        // contract
+        trie.load_proof(EIP1186ProofResponse {
+            address: "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+                .parse()
+                .unwrap(),
+            account_proof: vec![
+                "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080".parse().unwrap(),
+                "0xf869a03a7a2ee9b4f54ecbf2e04737a19215c0864d20c9a332db61d093e9ec95b2e87ab846f8440180a029cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748ca02e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16".parse().unwrap(),
+            ],
+            balance: "0x0".parse().unwrap(),
+            code_hash: "0x2e3b8d783952495f405666042a1ceb57bd6848afbbc1f2aad92bc2b5f8169a16"
+                .parse()
+                .unwrap(),
+            nonce: "0x1".parse().unwrap(),
+            storage_hash: "0x29cf2043d2a8fd3c4ed584f1afd2976a366f90a84446c1bd73e251e097b1748c"
+                .parse()
+                .unwrap(),
+            storage_proof: vec![
+                StorageProof {
+                    key: "0x0000000000000000000000000000000000000000000000000000000000000001".parse().unwrap(),
+                    value: "0x2".parse().unwrap(),
+                    proof: vec![
+                        "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc5808080808080a0236e8f61ecde6abfebc6c529441f782f62469d8a2cc47b7aace2c136bd3b1ff08080808080".parse().unwrap(),
+                        "0xe2a0310e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf602".parse().unwrap()
+                    ],
+                },
+                StorageProof {
+                    key: "0x0000000000000000000000000000000000000000000000000000000000000002".parse().unwrap(),
+                    value: "0x4".parse().unwrap(),
+                    proof: vec![
+                        "0xf85180808080a03f39d7bf4be8677b2d7db8f944e618380c443e7615adddd29b4cba751d7acdc5808080808080a0236e8f61ecde6abfebc6c529441f782f62469d8a2cc47b7aace2c136bd3b1ff08080808080".parse().unwrap(),
+                        "0xe2a0305787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace04".parse().unwrap()
+                    ],
+                }
+            ],
+        }).unwrap();
+
+        // tx sender
+        trie.load_proof(EIP1186ProofResponse {
+            address: "0x3736b9d9d35d8c4f41d98a412fe9211024453575"
+                .parse()
+                .unwrap(),
+            account_proof: vec![
+                "0xf90151a0bfa1a037624f2e96cc598c63c0db6249cb0e507c2015af3e2ecb8b16b58f92b7a0ab8cdb808c8303bb61fb48e276217be9770fa83ecf3f90f2234d558885f5abf1a0d5a5048c1d78dafd61d8181577c08d6cd2b52fde48040a676be755dc69a275db80a01a697e814758281972fcd13bc9707dbcd2f195986b05463d7b78426508445a04a0b5d7a91be5ee273cce27e2ad9a160d2faadd5a6ba518d384019b68728a4f62f4a0c2c799b60a0cd6acd42c1015512872e86c186bcf196e85061e76842f3b7cf86080a02e0d86c3befd177f574a20ac63804532889077e955320c9361cd10b7cc6f580980a06301b39b2ea8a44df8b0356120db64b788e71f52e1d7a6309d0d2e5b86fee7cb8080a01b7779e149cadf24d4ffb77ca7e11314b8db7097e4d70b2a173493153ca2e5a0a066a7662811491b3d352e969506b420d269e8b51a224f574b3b38b3463f43f0098080".parse().unwrap(),
+                "0xf889a03e19976962fea3751225213669050369b7cd26650bc43815007705e945b5aa57b866f86403a0ffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82a056e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421a0c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470".parse().unwrap(),
+            ],
+            balance: "0xffffffffffffffffffffffffffffffffffffffffffffffffffff546059ae3c82".parse().unwrap(),
+            code_hash: "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+                .parse()
+                .unwrap(),
+            nonce: "0x3".parse().unwrap(),
+            storage_hash: "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+                .parse()
+                .unwrap(),
+            storage_proof: vec![],
+        }).unwrap();
+
+        trie
+    }
+
+    #[test]
+    pub fn test_zkevm_state_entries_1() {
+        let trie = load_pre_state();
+        let entries = trie.to_state_entries().unwrap();
+
+        // in address order: tx sender then contract
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].address,
+            "0x3736b9d9d35d8c4f41d98a412fe9211024453575"
+                .parse::<Address>()
+                .unwrap()
+        );
+        assert_eq!(entries[0].nonce, U256::from(3));
+        assert!(entries[0].storage.is_empty());
+        assert_eq!(entries[1].nonce, U256::from(1));
+        assert_eq!(
+            entries[1].storage,
+            vec![
+                (U256::from(1), U256::from(2)),
+                (U256::from(2), U256::from(4))
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_zkevm_mpt_updates_1() {
+        let pre = load_pre_state();
+        let sender = "0x3736b9d9d35d8c4f41d98a412fe9211024453575"
+            .parse::<Address>()
+            .unwrap();
+        let contract = "0x730E01e70B028b44a9387119d78E1392E4848Cbc"
+            .parse::<Address>()
+            .unwrap();
+
+        let mut post = pre.clone();
+        post.account_trie.set_nonce(sender, U256::from(4)).unwrap();
+        post.set_storage_value(contract, U256::from(1), U256::from(5))
+            .unwrap();
+
+        assert!(pre.to_mpt_updates(&pre).unwrap().is_empty());
+        assert_eq!(
+            pre.to_mpt_updates(&post).unwrap(),
+            vec![
+                MptUpdate {
+                    key: MptKey::Account {
+                        address: sender,
+                        field: AccountField::Nonce
+                    },
+                    old_value: U256::from(3),
+                    new_value: U256::from(4),
+                },
+                MptUpdate {
+                    key: MptKey::Storage {
+                        address: contract,
+                        slot: U256::from(1)
+                    },
+                    old_value: U256::from(2),
+                    new_value: U256::from(5),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use ethers::types::{Address, H256, U256};
+use zk_proof_of_evm_exploit::{
+    error::Error,
+    state_root::{account_proof::AccountProof, state_trie::StateTrie},
+};
+
+// geth --dev state of test_state_1, a contract with slot[1] = 2 and slot[2] = 4 and
+// the sender that deployed it
+const STATE_ROOT: &str = "0x60bfaa2e6e61adcd645ce3aefc05c3bda2ed31f95fdd8bd5422dc2b8c78ae909";
+const CONTRACT: &str = "0x730E01e70B028b44a9387119d78E1392E4848Cbc";
+const SENDER: &str = "0x3736b9d9d35d8c4f41d98a412fe9211024453575";
+
+fn testdata(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata")
+        .join(path)
+}
+
+// the error wrapped in the FixtureError, after checking it names `file`
+fn fixture_error(err: Error, file: &str) -> Error {
+    match err {
+        Error::FixtureError(path, err) => {
+            assert!(path.ends_with(file), "{:?} is not {}", path, file);
+            *err
+        }
+        err => panic!("expected a fixture error, got {}", err),
+    }
+}
+
+#[test]
+fn test_load_fixture_dir_1() {
+    let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+    trie.load_fixture_dir(&testdata("eth_getProof")).unwrap();
+
+    let contract: Address = CONTRACT.parse().unwrap();
+    let sender: Address = SENDER.parse().unwrap();
+    assert_eq!(
+        trie.account_trie.get_account_data(sender).unwrap().nonce,
+        U256::from(3)
+    );
+    let storage_root = trie.account_trie.storage_root_for(contract).unwrap();
+    let storage_trie = trie.get_storage_trie(storage_root);
+    assert_eq!(storage_trie.get_value(1.into()).unwrap(), U256::from(2));
+    assert_eq!(storage_trie.get_value(2.into()).unwrap(), U256::from(4));
+    assert_eq!(trie.root(), Some(STATE_ROOT.parse().unwrap()));
+}
+
+#[test]
+fn test_export_eip1186_1_json() {
+    let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+    trie.load_fixture_dir(&testdata("eth_getProof")).unwrap();
+
+    let response = trie
+        .export_eip1186(CONTRACT.parse().unwrap(), &[1.into(), 2.into()])
+        .unwrap();
+    let json = AccountProof::from_eip1186(STATE_ROOT.parse().unwrap(), response)
+        .to_json()
+        .unwrap();
+    let expected: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(testdata("eth_getProof/contract.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(json, expected);
+}
+
+#[test]
+fn test_load_fixture_file_1_malformed() {
+    let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+    let err = trie
+        .load_fixture_file(&testdata("malformed.json"))
+        .unwrap_err();
+    assert!(err.to_string().contains("malformed.json"));
+    assert!(matches!(
+        fixture_error(err, "malformed.json"),
+        Error::JsonError(_)
+    ));
+}
+
+#[test]
+fn test_load_fixture_file_2_wrong_root() {
+    let mut trie = StateTrie::from_root(H256::repeat_byte(0x11));
+    let err = trie
+        .load_fixture_file(&testdata("eth_getProof/sender.json"))
+        .unwrap_err();
+    assert!(matches!(
+        fixture_error(err, "sender.json"),
+        Error::PreStateRootMismatch(..)
+    ));
+}
+
+#[test]
+fn test_load_fixture_file_3_duplicate_account() {
+    let mut trie = StateTrie::from_root(STATE_ROOT.parse().unwrap());
+    trie.load_fixture_file(&testdata("eth_getProof/contract.json"))
+        .unwrap();
+    let err = trie
+        .load_fixture_dir(&testdata("eth_getProof"))
+        .unwrap_err();
+    match fixture_error(err, "contract.json") {
+        Error::DuplicateAccount(address) => assert_eq!(address, CONTRACT.parse().unwrap()),
+        err => panic!("expected a duplicate account, got {}", err),
+    }
+}